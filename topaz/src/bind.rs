@@ -12,4 +12,62 @@ extern "C" {
 
     #[wasm_bindgen(js_namespace = console)]
     pub fn warn(s: &str);
-}
\ No newline at end of file
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn info(s: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn debug(s: &str);
+
+    /// `console.error` with a value the console renders as an expandable object.
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    pub fn error_with_value(s: &str, value: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console, js_name = warn)]
+    pub fn warn_with_value(s: &str, value: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console, js_name = info)]
+    pub fn info_with_value(s: &str, value: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console, js_name = debug)]
+    pub fn debug_with_value(s: &str, value: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn table(data: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console, js_name = table)]
+    pub fn table_with_columns(data: &JsValue, columns: &JsValue);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn group(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = groupCollapsed)]
+    pub fn group_collapsed(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = groupEnd)]
+    pub fn group_end();
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn time(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = timeLog)]
+    pub fn time_log(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = timeEnd)]
+    pub fn time_end(label: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn count(label: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = countReset)]
+    pub fn count_reset(label: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn assert(condition: bool, s: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn trace(s: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn dir(value: &JsValue);
+}
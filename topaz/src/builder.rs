@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::panic::PanicHookInfo;
+use crate::dom::Element;
+use crate::global::fetch::{self, Middleware};
+use crate::logger::{self, LevelFilter};
+use crate::panic::{self, PanicReport};
+use crate::print::{self, Flush};
+
+/// What happens when the app panics. The panic message is always preceded by any pending
+/// [`print!`](crate::print!) output.
+pub enum PanicHook {
+    /// Write the panic to `console.error`. With the `capture-panic` feature, this uses
+    /// `console_error_panic_hook`, which includes the JavaScript stack trace.
+    Console,
+    /// Leave whatever panic hook is already installed.
+    Keep,
+    /// Write to the console and cover the page with the panic message, location and backtrace.
+    /// Meant for development. See the [`panic`](crate::panic) module.
+    Overlay,
+    /// Write to the console, cover the page with a "something went wrong" message, and pass the
+    /// panic to the callback, e.g. to report it. Meant for production. See
+    /// [`PanicHook::fallback`].
+    Fallback(ReportFn),
+    /// Call this instead.
+    Custom(Box<dyn Fn(&PanicHookInfo) + Send + Sync + 'static>),
+}
+
+pub type ReportFn = Box<dyn Fn(&PanicReport) + Send + Sync + 'static>;
+
+impl fmt::Debug for PanicHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PanicHook::Console => f.write_str("Console"),
+            PanicHook::Keep => f.write_str("Keep"),
+            PanicHook::Overlay => f.write_str("Overlay"),
+            PanicHook::Fallback(_) => f.write_str("Fallback(..)"),
+            PanicHook::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl PanicHook {
+    /// [`PanicHook::Fallback`] with `report` called for each panic.
+    pub fn fallback(report: impl Fn(&PanicReport) + Send + Sync + 'static) -> Self {
+        PanicHook::Fallback(Box::new(report))
+    }
+
+    fn install(self) {
+        if !matches!(self, PanicHook::Keep) {
+            print::panic_hook_replaced();
+        }
+        match self {
+            PanicHook::Console => std::panic::set_hook(Box::new(console_hook)),
+            PanicHook::Keep => {}
+            PanicHook::Overlay => std::panic::set_hook(Box::new(|info| {
+                console_hook(info);
+                panic::render_overlay(&PanicReport::from_info(info));
+            })),
+            PanicHook::Fallback(report) => std::panic::set_hook(Box::new(move |info| {
+                console_hook(info);
+                panic::render_fallback();
+                report(&PanicReport::from_info(info));
+            })),
+            PanicHook::Custom(hook) => std::panic::set_hook(hook),
+        }
+    }
+}
+
+#[cfg(feature = "capture-panic")]
+fn console_hook(info: &PanicHookInfo) {
+    console_error_panic_hook::hook(info);
+}
+
+#[cfg(not(feature = "capture-panic"))]
+fn console_hook(info: &PanicHookInfo) {
+    if cfg!(target_arch = "wasm32") {
+        crate::bind::error(&info.to_string());
+    } else {
+        std::eprintln!("{}", info);
+    }
+}
+
+/// Middleware isn't `Debug`, so [`Builder`] only shows how many there are.
+#[derive(Default)]
+struct Middlewares(Vec<Rc<dyn Middleware>>);
+
+impl fmt::Debug for Middlewares {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} middleware]", self.0.len())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Root {
+    Id(String),
+    Element(Element),
+}
+
+thread_local! {
+    static ROOT: RefCell<Option<Root>> = const { RefCell::new(None) };
+}
+
+/// The element the app renders into, as set with [`Builder::root_id`] or
+/// [`Builder::root_element`]. Defaults to `document.body`.
+///
+/// Panics if the root was set by id and no element has that id.
+pub fn root() -> Element {
+    let root = ROOT.with(|r| r.borrow().clone());
+    match root {
+        Some(Root::Element(element)) => element,
+        Some(Root::Id(id)) => crate::global::document()
+            .get_element_by_id(&id)
+            .unwrap_or_else(|| panic!("no element with id {:?} to use as the root", id)),
+        None => Element::new(crate::global::document().body().into()),
+    }
+}
+
+/// Append `node` to the [`root`] element, e.g. the app's top-level element once it's built.
+pub fn mount(node: &web_sys::Node) {
+    root().inner.append_child(node).expect("failed to mount into the root element");
+}
+
+/// Configure what [`start`](crate::start) sets up, for apps that need something other than the
+/// defaults. `topaz::start()` is the same as `Builder::new().start()`.
+///
+/// ```
+/// use std::time::Duration;
+/// use topaz::global::fetch::Cache;
+/// use topaz::logger::LevelFilter;
+/// use topaz::print::Flush;
+/// use topaz::{Builder, PanicHook};
+///
+/// Builder::new()
+///     .flush(Flush::After(Duration::from_millis(100)))
+///     .panic_hook(PanicHook::Keep)
+///     .log_level(LevelFilter::Warn)
+///     .middleware(Cache::new(Duration::from_secs(60)))
+///     .root_id("app")
+///     .start();
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    capture_print: bool,
+    flush: Flush,
+    panic_hook: PanicHook,
+    log_level: Option<LevelFilter>,
+    middleware: Middlewares,
+    root: Option<Root>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            capture_print: cfg!(feature = "capture-print"),
+            flush: Flush::default(),
+            panic_hook: if cfg!(feature = "capture-panic") { PanicHook::Console } else { PanicHook::Keep },
+            log_level: None,
+            middleware: Middlewares::default(),
+            root: None,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redirect `std`'s `print!` family to the console, as with [`hook_println`](crate::hook_println).
+    /// On by default with the `capture-print` feature, and has no effect without it.
+    pub fn capture_print(mut self, capture_print: bool) -> Self {
+        self.capture_print = capture_print;
+        self
+    }
+
+    /// When partial lines of [`print!`](crate::print!) output are written. See [`Flush`].
+    pub fn flush(mut self, flush: Flush) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Defaults to [`PanicHook::Console`] with the `capture-panic` feature, and [`PanicHook::Keep`]
+    /// without it.
+    pub fn panic_hook(mut self, panic_hook: PanicHook) -> Self {
+        self.panic_hook = panic_hook;
+        self
+    }
+
+    /// The most verbose level written by the `log` and `tracing` backends. See
+    /// [`logger::set_max_level`].
+    pub fn log_level(mut self, log_level: LevelFilter) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    /// Add `middleware` to the `fetch` chain when starting, after any added before. See
+    /// [`fetch::add_middleware`].
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.0.push(Rc::new(middleware));
+        self
+    }
+
+    /// [`mount`] into the element with this id instead of `document.body`. See [`root`].
+    pub fn root_id(mut self, id: &str) -> Self {
+        self.root = Some(Root::Id(id.to_string()));
+        self
+    }
+
+    /// [`mount`] into this element instead of `document.body`. See [`root`].
+    pub fn root_element(mut self, element: Element) -> Self {
+        self.root = Some(Root::Element(element));
+        self
+    }
+
+    pub fn start(self) {
+        #[cfg(feature = "capture-print")]
+        if self.capture_print {
+            crate::hook_println();
+        }
+        print::set_flush(self.flush);
+        self.panic_hook.install();
+        print::flush_on_panic();
+        if let Some(log_level) = self.log_level {
+            logger::set_max_level(log_level);
+        }
+        logger::init();
+        for middleware in self.middleware.0 {
+            fetch::push_middleware(middleware);
+        }
+        if let Some(root) = self.root {
+            ROOT.with(|r| *r.borrow_mut() = Some(root));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `start` installs process-wide hooks and backends, which would leak into other tests, so
+    /// only the configuration is checked here.
+    #[test]
+    fn test_builder() {
+        let builder = Builder::new()
+            .capture_print(false)
+            .flush(Flush::Manual)
+            .panic_hook(PanicHook::Keep)
+            .log_level(LevelFilter::Warn)
+            .middleware(fetch::Cache::new(std::time::Duration::from_secs(60)))
+            .root_id("app");
+        assert!(!builder.capture_print);
+        assert_eq!(builder.flush, Flush::Manual);
+        assert!(matches!(builder.panic_hook, PanicHook::Keep));
+        assert_eq!(builder.log_level, Some(LevelFilter::Warn));
+        assert_eq!(builder.middleware.0.len(), 1);
+        assert!(matches!(builder.root, Some(Root::Id(id)) if id == "app"));
+    }
+}
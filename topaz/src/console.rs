@@ -0,0 +1,274 @@
+//! The rest of the browser's `console` API, for debugging structured data without stringifying
+//! it first.
+//!
+//! ```
+//! use serde::Serialize;
+//! use topaz::console;
+//!
+//! #[derive(Serialize)]
+//! struct Row { name: &'static str, score: u32 }
+//!
+//! let _group = console::group("Scores");
+//! let _timer = console::time("render");
+//! console::table(&[Row { name: "ferris", score: 10 }, Row { name: "corro", score: 7 }]);
+//! console::count("renders");
+//! // `console.timeEnd` and `console.groupEnd` run here, as the guards are dropped.
+//! ```
+//!
+//! Values are converted with `serde`, through JSON, so the console shows them as plain objects
+//! and arrays. If a value fails to serialize, the error is written with `console.error` instead.
+//!
+//! Every call first flushes pending [`print!`](crate::print!) output, so entries stay in order.
+//! Off wasm, e.g. in native tests, a plain text version is written to stderr.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use crate::bind;
+use crate::global::now_millis;
+
+thread_local! {
+    /// Only tracked off wasm, where there's no console to do it for us.
+    static GROUP_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static COUNTS: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+fn is_browser() -> bool {
+    cfg!(target_arch = "wasm32")
+}
+
+/// Write a line to stderr, indented by the current group depth.
+fn native(line: &str) {
+    let indent = "  ".repeat(GROUP_DEPTH.with(|d| d.get()));
+    for line in line.lines() {
+        eprintln!("{}{}", indent, line);
+    }
+}
+
+/// Serialize to a JS value by way of JSON, so maps become objects rather than `Map`s.
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, serde_json::Error> {
+    let json = serde_json::to_string(value)?;
+    Ok(js_sys::JSON::parse(&json).expect("serde_json produced invalid JSON"))
+}
+
+fn serialize_error(what: &str, e: serde_json::Error) {
+    let message = format!("topaz::console::{}: failed to serialize value: {}", what, e);
+    if is_browser() {
+        bind::error(&message);
+    } else {
+        native(&message);
+    }
+}
+
+/// `console.table`, with one row per element. Elements that serialize to objects get one column
+/// per field.
+pub fn table<T: Serialize>(rows: &[T]) {
+    crate::print::flush();
+    if !is_browser() {
+        return native_table(rows);
+    }
+    match to_js(rows) {
+        Ok(rows) => bind::table(&rows),
+        Err(e) => serialize_error("table", e),
+    }
+}
+
+/// Like [`table`], but only shows the given columns, in that order.
+pub fn table_with_columns<T: Serialize>(rows: &[T], columns: &[&str]) {
+    crate::print::flush();
+    if !is_browser() {
+        return native_table(rows);
+    }
+    let result = to_js(rows).and_then(|rows| Ok((rows, to_js(columns)?)));
+    match result {
+        Ok((rows, columns)) => bind::table_with_columns(&rows, &columns),
+        Err(e) => serialize_error("table", e),
+    }
+}
+
+fn native_table<T: Serialize>(rows: &[T]) {
+    for (i, row) in rows.iter().enumerate() {
+        match serde_json::to_string(row) {
+            Ok(row) => native(&format!("{}: {}", i, row)),
+            Err(e) => return serialize_error("table", e),
+        }
+    }
+}
+
+/// `console.dir` of a serialized value.
+pub fn dir<T: Serialize + ?Sized>(value: &T) {
+    crate::print::flush();
+    if !is_browser() {
+        match serde_json::to_string_pretty(value) {
+            Ok(value) => native(&value),
+            Err(e) => serialize_error("dir", e),
+        }
+        return;
+    }
+    match to_js(value) {
+        Ok(value) => bind::dir(&value),
+        Err(e) => serialize_error("dir", e),
+    }
+}
+
+/// Ends its console group when dropped. Returned by [`group`] and [`group_collapsed`].
+#[must_use = "the group ends as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct Group {
+    _private: (),
+}
+
+/// Start a console group. Entries are nested under `label` until the returned guard is dropped.
+pub fn group(label: &str) -> Group {
+    crate::print::flush();
+    if is_browser() {
+        bind::group(label);
+    } else {
+        native(label);
+        GROUP_DEPTH.with(|d| d.set(d.get() + 1));
+    }
+    Group { _private: () }
+}
+
+/// Like [`group`], but collapsed until it's clicked on.
+pub fn group_collapsed(label: &str) -> Group {
+    crate::print::flush();
+    if is_browser() {
+        bind::group_collapsed(label);
+    } else {
+        native(label);
+        GROUP_DEPTH.with(|d| d.set(d.get() + 1));
+    }
+    Group { _private: () }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        crate::print::flush();
+        if is_browser() {
+            bind::group_end();
+        } else {
+            GROUP_DEPTH.with(|d| d.set(d.get().saturating_sub(1)));
+        }
+    }
+}
+
+/// A running console timer, started by [`time`]. Writes the elapsed time with `console.timeEnd`
+/// when dropped.
+#[must_use = "the timer ends as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct Timer {
+    label: String,
+    start: u64,
+}
+
+/// Start a console timer with `console.time`.
+pub fn time(label: &str) -> Timer {
+    if is_browser() {
+        bind::time(label);
+    }
+    Timer { label: label.to_string(), start: now_millis() }
+}
+
+impl Timer {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Write the time elapsed so far with `console.timeLog`, without stopping the timer.
+    pub fn log(&self) {
+        crate::print::flush();
+        if is_browser() {
+            bind::time_log(&self.label);
+        } else {
+            native(&self.elapsed_line());
+        }
+    }
+
+    fn elapsed_line(&self) -> String {
+        format!("{}: {}ms", self.label, now_millis().saturating_sub(self.start))
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        crate::print::flush();
+        if is_browser() {
+            bind::time_end(&self.label);
+        } else {
+            native(&format!("{} - timer ended", self.elapsed_line()));
+        }
+    }
+}
+
+/// `console.count`: write how many times this has been called with `label`.
+pub fn count(label: &str) {
+    crate::print::flush();
+    if is_browser() {
+        return bind::count(label);
+    }
+    let count = COUNTS.with(|c| {
+        let mut counts = c.borrow_mut();
+        let count = counts.entry(label.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    });
+    native(&format!("{}: {}", label, count));
+}
+
+/// `console.countReset`: start counting `label` from zero again.
+pub fn count_reset(label: &str) {
+    if is_browser() {
+        return bind::count_reset(label);
+    }
+    COUNTS.with(|c| c.borrow_mut().remove(label));
+}
+
+/// `console.assert`: write `message` as an error if `condition` is false. Unlike `assert!`, this
+/// doesn't panic.
+pub fn assert(condition: bool, message: &str) {
+    if condition {
+        return;
+    }
+    crate::print::flush();
+    if is_browser() {
+        bind::assert(condition, message);
+    } else {
+        native(&format!("Assertion failed: {}", message));
+    }
+}
+
+/// `console.trace`: write `message` along with the JavaScript stack trace.
+pub fn trace(message: &str) {
+    crate::print::flush();
+    if is_browser() {
+        bind::trace(message);
+    } else {
+        native(&format!("Trace: {}", message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_depth() {
+        {
+            let _outer = group("outer");
+            let _inner = group_collapsed("inner");
+            assert_eq!(GROUP_DEPTH.with(|d| d.get()), 2);
+        }
+        assert_eq!(GROUP_DEPTH.with(|d| d.get()), 0);
+    }
+
+    #[test]
+    fn test_count() {
+        count("clicks");
+        count("clicks");
+        assert_eq!(COUNTS.with(|c| c.borrow()["clicks"]), 2);
+        count_reset("clicks");
+        assert!(COUNTS.with(|c| c.borrow().get("clicks").is_none()));
+    }
+}
@@ -9,8 +9,11 @@ pub struct Element {
 }
 
 
-fn add_event_listener_boxed(element: &web_sys::Element, event_name: &str, callback: Box<dyn FnMut(web_sys::Event)>) -> Closure<dyn FnMut(Event)> {
-    let closure = Closure::wrap(callback);
+fn add_event_listener_boxed(element: &web_sys::Element, event_name: &str, mut callback: Box<dyn FnMut(web_sys::Event)>) -> Closure<dyn FnMut(Event)> {
+    let closure = Closure::wrap(Box::new(move |event| {
+        callback(event);
+        crate::print::flush_captured();
+    }) as Box<dyn FnMut(Event)>);
     element.add_event_listener_with_callback(event_name, closure.as_ref().unchecked_ref()).unwrap();
     closure
 }
@@ -0,0 +1,105 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use crate::global::callbacks::{self, Registry};
+use crate::global::window::web_sys_window;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationFrameId(u32);
+
+thread_local! {
+    static FRAMES: Registry = RefCell::new(HashMap::new());
+}
+
+fn request_animation_frame_boxed(callback: Box<dyn FnOnce(f64) + 'static>) -> AnimationFrameId {
+    let handle = callbacks::schedule(&FRAMES, move |frame_time| {
+        callback(frame_time.as_f64().unwrap_or(0.0));
+    }, |function| {
+        web_sys_window()
+            .request_animation_frame(function)
+            .expect("request_animation_frame failed") as u32
+    });
+    AnimationFrameId(handle)
+}
+
+/// Run `callback` before the next repaint. It receives the frame's timestamp in milliseconds,
+/// on the same clock as `performance.now()`.
+///
+/// # Examples
+///
+/// ```no_run
+/// topaz::global::request_animation_frame(|frame_time| {
+///     println!("Painting frame at {}ms", frame_time);
+/// });
+/// ```
+#[inline]
+pub fn request_animation_frame(callback: impl FnOnce(f64) + 'static) -> AnimationFrameId {
+    request_animation_frame_boxed(Box::new(callback))
+}
+
+pub fn cancel_animation_frame(frame_id: AnimationFrameId) {
+    let _ = web_sys_window().cancel_animation_frame(frame_id.0 as i32);
+    callbacks::release(&FRAMES, frame_id.0);
+}
+
+/// A running [`animation_loop`]. The loop stops when this is dropped.
+#[must_use = "the animation loop stops as soon as it is dropped"]
+pub struct AnimationLoop {
+    state: Rc<LoopState>,
+}
+
+struct LoopState {
+    callback: RefCell<Box<dyn FnMut(f64, f64)>>,
+    last_frame_time: Cell<Option<f64>>,
+    frame_id: Cell<Option<AnimationFrameId>>,
+}
+
+impl LoopState {
+    fn schedule(state: &Rc<LoopState>) {
+        let weak: Weak<LoopState> = Rc::downgrade(state);
+        let frame_id = request_animation_frame(move |frame_time| {
+            let state = match weak.upgrade() {
+                Some(state) => state,
+                None => return,
+            };
+            state.frame_id.set(None);
+            let delta = state.last_frame_time.replace(Some(frame_time))
+                .map(|last| frame_time - last)
+                .unwrap_or(0.0);
+            (state.callback.borrow_mut())(frame_time, delta);
+            LoopState::schedule(&state);
+        });
+        state.frame_id.set(Some(frame_id));
+    }
+}
+
+/// Call `callback` on every frame with the frame's timestamp and the milliseconds since the
+/// previous frame (`0.0` on the first frame), until the returned [`AnimationLoop`] is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// let mut angle = 0.0;
+/// let spinner = topaz::global::animation_loop(move |_frame_time, delta| {
+///     angle = (angle + delta * 0.36) % 360.0;
+///     // Redraw with `angle`...
+/// });
+/// // Keep `spinner` alive for as long as it should spin.
+/// ```
+pub fn animation_loop(callback: impl FnMut(f64, f64) + 'static) -> AnimationLoop {
+    let state = Rc::new(LoopState {
+        callback: RefCell::new(Box::new(callback)),
+        last_frame_time: Cell::new(None),
+        frame_id: Cell::new(None),
+    });
+    LoopState::schedule(&state);
+    AnimationLoop { state }
+}
+
+impl Drop for AnimationLoop {
+    fn drop(&mut self) {
+        if let Some(frame_id) = self.state.frame_id.take() {
+            cancel_animation_frame(frame_id);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! One-shot browser callbacks, like timeouts, idle callbacks and animation frames.
+//!
+//! Each kind keeps the closures it has handed to the browser in a [`Registry`], keyed by the
+//! browser's handle, until they run or are cancelled. That way they are freed instead of leaked
+//! with `Closure::forget`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::thread::LocalKey;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen::prelude::Closure;
+
+/// Closures for callbacks that haven't run or been cancelled yet.
+pub(crate) type Registry = RefCell<HashMap<u32, Closure<dyn FnMut(JsValue)>>>;
+
+/// Hand `callback` to the browser with `schedule`, which returns the browser's handle for it.
+/// The callback gets whatever argument the browser passes, `undefined` if none.
+pub(crate) fn schedule(
+    registry: &'static LocalKey<Registry>,
+    callback: impl FnOnce(JsValue) + 'static,
+    schedule: impl FnOnce(&js_sys::Function) -> u32,
+) -> u32 {
+    let handle = Rc::new(Cell::new(None));
+    let mut callback = Some(callback);
+    let closure = Closure::wrap(Box::new({
+        let handle = handle.clone();
+        move |arg: JsValue| {
+            // Keep the closure alive until the callback returns, since we're running inside it.
+            let _closure = handle.get().and_then(|handle| registry.with(|r| r.borrow_mut().remove(&handle)));
+            if let Some(callback) = callback.take() {
+                callback(arg);
+            }
+            crate::print::flush_captured();
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    let id = schedule(closure.as_ref().unchecked_ref());
+    handle.set(Some(id));
+    registry.with(|r| r.borrow_mut().insert(id, closure));
+    id
+}
+
+/// Free the closure of a callback that was cancelled with the browser. It's dropped after the
+/// registry is released, so it may itself hold guards that cancel other callbacks.
+pub(crate) fn release(registry: &'static LocalKey<Registry>, handle: u32) {
+    let closure = registry.with(|r| r.borrow_mut().remove(&handle));
+    drop(closure);
+}
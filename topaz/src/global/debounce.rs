@@ -0,0 +1,357 @@
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use crate::global::timer::{set_timeout_guard, TimeoutGuard};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Debounce,
+    Throttle,
+}
+
+type Callback<T> = Box<dyn FnMut(T)>;
+
+/// State shared by [`Debounced`] and [`Throttled`]. They differ only in what a call does while a
+/// timer is pending, and whether the timer restarts after a trailing call.
+struct Limiter<T> {
+    mode: Mode,
+    millis: u32,
+    leading: Cell<bool>,
+    trailing: Cell<bool>,
+    /// Taken out while it runs, so the callback can call its own limiter.
+    callback: RefCell<Option<Callback<T>>>,
+    pending: RefCell<Option<T>>,
+    timer: RefCell<Option<TimeoutGuard>>,
+}
+
+impl<T: 'static> Limiter<T> {
+    fn new(mode: Mode, callback: Callback<T>, millis: u32, leading: bool, trailing: bool) -> Rc<Self> {
+        Rc::new(Limiter {
+            mode,
+            millis,
+            leading: Cell::new(leading),
+            trailing: Cell::new(trailing),
+            callback: RefCell::new(Some(callback)),
+            pending: RefCell::new(None),
+            timer: RefCell::new(None),
+        })
+    }
+
+    fn invoke(self: &Rc<Self>, arg: T) {
+        let callback = self.callback.borrow_mut().take();
+        match callback {
+            Some(mut callback) => {
+                callback(arg);
+                *self.callback.borrow_mut() = Some(callback);
+            }
+            // Called from inside the callback, so run it as a trailing call once the timer fires.
+            None => {
+                *self.pending.borrow_mut() = Some(arg);
+                if self.timer.borrow().is_none() {
+                    self.start_timer();
+                }
+            }
+        }
+    }
+
+    fn start_timer(self: &Rc<Self>) {
+        let weak: Weak<Self> = Rc::downgrade(self);
+        let guard = set_timeout_guard(move || {
+            if let Some(limiter) = weak.upgrade() {
+                limiter.fire();
+            }
+        }, self.millis);
+        // Replacing the guard clears the previous timer, which restarts a debounce.
+        let previous = self.timer.replace(Some(guard));
+        drop(previous);
+    }
+
+    fn call(self: &Rc<Self>, arg: T) {
+        let idle = self.timer.borrow().is_none();
+        match self.mode {
+            Mode::Debounce => {
+                if idle && self.leading.get() {
+                    self.invoke(arg);
+                } else if self.trailing.get() {
+                    *self.pending.borrow_mut() = Some(arg);
+                }
+                self.start_timer();
+            }
+            Mode::Throttle => {
+                if idle {
+                    if self.leading.get() {
+                        self.invoke(arg);
+                    } else if self.trailing.get() {
+                        *self.pending.borrow_mut() = Some(arg);
+                    }
+                    self.start_timer();
+                } else if self.trailing.get() {
+                    *self.pending.borrow_mut() = Some(arg);
+                }
+            }
+        }
+    }
+
+    fn fire(self: &Rc<Self>) {
+        if let Some(guard) = self.timer.borrow_mut().take() {
+            guard.forget();
+        }
+        let pending = self.pending.borrow_mut().take();
+        if let Some(arg) = pending {
+            // A throttle stays cooling down after a trailing call, so calls keep being spaced out.
+            if self.mode == Mode::Throttle {
+                self.start_timer();
+            }
+            self.invoke(arg);
+        }
+    }
+
+    fn flush(self: &Rc<Self>) {
+        let timer = self.timer.borrow_mut().take();
+        drop(timer);
+        let pending = self.pending.borrow_mut().take();
+        if let Some(arg) = pending {
+            self.invoke(arg);
+        }
+    }
+
+    fn cancel(&self) {
+        let timer = self.timer.borrow_mut().take();
+        drop(timer);
+        self.pending.borrow_mut().take();
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.borrow().is_some()
+    }
+}
+
+/// A function that only runs once calls stop arriving for a while. Returned by [`debounce`].
+///
+/// Clones share the same timer, so clone it into event listeners freely.
+pub struct Debounced<T> {
+    inner: Rc<Limiter<T>>,
+}
+
+/// Wrap `callback` so that it runs `millis` after the last call, with the last call's argument.
+/// Calls in between restart the wait.
+///
+/// # Examples
+///
+/// ```no_run
+/// let input = topaz::global::document().get_element_by_id("search").unwrap();
+/// let search = topaz::global::debounce(|event: web_sys::Event| {
+///     println!("Searching for {:?}", event.target());
+/// }, 300);
+/// input.add_permanent_event_listener("input", search.callback());
+/// ```
+pub fn debounce<T: 'static>(callback: impl FnMut(T) + 'static, millis: u32) -> Debounced<T> {
+    Debounced {
+        inner: Limiter::new(Mode::Debounce, Box::new(callback), millis, false, true),
+    }
+}
+
+impl<T: 'static> Debounced<T> {
+    /// Also run on the first call of a burst, instead of waiting. Defaults to `false`.
+    pub fn leading(self, leading: bool) -> Self {
+        self.inner.leading.set(leading);
+        self
+    }
+
+    /// Run at the end of a burst. Defaults to `true`.
+    pub fn trailing(self, trailing: bool) -> Self {
+        self.inner.trailing.set(trailing);
+        self
+    }
+
+    pub fn call(&self, arg: T) {
+        self.inner.call(arg);
+    }
+
+    /// A closure that calls this, e.g. to pass to `Element::add_permanent_event_listener`.
+    pub fn callback(&self) -> impl FnMut(T) + 'static {
+        let debounced = self.clone();
+        move |arg| debounced.call(arg)
+    }
+
+    /// Run the pending call now instead of waiting.
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    /// Drop the pending call, if any.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Whether a trailing call is waiting to run.
+    pub fn is_pending(&self) -> bool {
+        self.inner.is_pending()
+    }
+}
+
+impl<T> Clone for Debounced<T> {
+    fn clone(&self) -> Self {
+        Debounced { inner: self.inner.clone() }
+    }
+}
+
+/// A function that runs at most once per interval. Returned by [`throttle`].
+///
+/// Clones share the same timer, so clone it into event listeners freely.
+pub struct Throttled<T> {
+    inner: Rc<Limiter<T>>,
+}
+
+/// Wrap `callback` so that it runs at most once every `millis`. The first call runs right away,
+/// and the last call during the wait runs when it's over.
+///
+/// # Examples
+///
+/// ```no_run
+/// let article = topaz::global::document().get_element_by_id("article").unwrap();
+/// let on_scroll = topaz::global::throttle(|_: web_sys::Event| {
+///     println!("Updating the table of contents");
+/// }, 100);
+/// article.add_permanent_event_listener("scroll", on_scroll.callback());
+/// ```
+pub fn throttle<T: 'static>(callback: impl FnMut(T) + 'static, millis: u32) -> Throttled<T> {
+    Throttled {
+        inner: Limiter::new(Mode::Throttle, Box::new(callback), millis, true, true),
+    }
+}
+
+impl<T: 'static> Throttled<T> {
+    /// Run on the first call, instead of waiting for the interval. Defaults to `true`.
+    pub fn leading(self, leading: bool) -> Self {
+        self.inner.leading.set(leading);
+        self
+    }
+
+    /// Run the last call made during the interval once it's over. Defaults to `true`.
+    pub fn trailing(self, trailing: bool) -> Self {
+        self.inner.trailing.set(trailing);
+        self
+    }
+
+    pub fn call(&self, arg: T) {
+        self.inner.call(arg);
+    }
+
+    /// A closure that calls this, e.g. to pass to `Element::add_permanent_event_listener`.
+    pub fn callback(&self) -> impl FnMut(T) + 'static {
+        let throttled = self.clone();
+        move |arg| throttled.call(arg)
+    }
+
+    /// Run the pending call now instead of waiting, and end the interval.
+    pub fn flush(&self) {
+        self.inner.flush();
+    }
+
+    /// Drop the pending call, if any, and end the interval.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Whether a trailing call is waiting to run.
+    pub fn is_pending(&self) -> bool {
+        self.inner.is_pending()
+    }
+}
+
+impl<T> Clone for Throttled<T> {
+    fn clone(&self) -> Self {
+        Throttled { inner: self.inner.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::global::{set_clock, VirtualClock};
+
+    fn install() -> (VirtualClock, Rc<RefCell<Vec<u32>>>) {
+        let clock = VirtualClock::new();
+        set_clock(clock.clone());
+        (clock, Rc::new(RefCell::new(Vec::new())))
+    }
+
+    fn record(calls: &Rc<RefCell<Vec<u32>>>) -> impl FnMut(u32) + 'static {
+        let calls = calls.clone();
+        move |arg| calls.borrow_mut().push(arg)
+    }
+
+    #[test]
+    fn test_debounce() {
+        let (clock, calls) = install();
+        let debounced = debounce(record(&calls), 100);
+        let mut callback = debounced.callback();
+        callback(1);
+        clock.advance(Duration::from_millis(50));
+        callback(2);
+        clock.advance(Duration::from_millis(99));
+        assert!(calls.borrow().is_empty());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(*calls.borrow(), [2]);
+
+        debounced.call(3);
+        debounced.cancel();
+        debounced.call(4);
+        debounced.flush();
+        clock.run_until_idle();
+        assert_eq!(*calls.borrow(), [2, 4]);
+    }
+
+    #[test]
+    fn test_debounce_leading() {
+        let (clock, calls) = install();
+        let debounced = debounce(record(&calls), 100).leading(true).trailing(false);
+        debounced.call(1);
+        debounced.call(2);
+        clock.advance(Duration::from_millis(100));
+        debounced.call(3);
+        assert_eq!(*calls.borrow(), [1, 3]);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let (clock, calls) = install();
+        let throttled = throttle(record(&calls), 100);
+        throttled.call(1);
+        throttled.call(2);
+        throttled.call(3);
+        assert_eq!(*calls.borrow(), [1]);
+        assert!(throttled.is_pending());
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(*calls.borrow(), [1, 3]);
+        // Still cooling down after the trailing call.
+        throttled.call(4);
+        assert_eq!(*calls.borrow(), [1, 3]);
+        clock.run_until_idle();
+        assert_eq!(*calls.borrow(), [1, 3, 4]);
+        assert!(clock.pending().is_empty());
+    }
+
+    #[test]
+    fn test_reentrant() {
+        let (clock, calls) = install();
+        let throttled: Rc<RefCell<Option<Throttled<u32>>>> = Rc::new(RefCell::new(None));
+        let callback = {
+            let throttled = throttled.clone();
+            let mut record = record(&calls);
+            move |arg| {
+                record(arg);
+                if arg < 2 {
+                    throttled.borrow().as_ref().unwrap().call(arg + 1);
+                }
+            }
+        };
+        *throttled.borrow_mut() = Some(throttle(callback, 100));
+        throttled.borrow().as_ref().unwrap().call(1);
+        assert_eq!(*calls.borrow(), [1]);
+        clock.run_until_idle();
+        assert_eq!(*calls.borrow(), [1, 2]);
+        throttled.borrow_mut().take();
+    }
+}
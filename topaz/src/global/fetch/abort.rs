@@ -0,0 +1,95 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use crate::global::fetch::FetchError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AbortReason {
+    Aborted,
+    Timeout,
+}
+
+#[derive(Default)]
+struct AbortState {
+    reason: Cell<Option<AbortReason>>,
+    callbacks: RefCell<Vec<Box<dyn FnOnce()>>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+/// Cancels an in-flight `fetch`. Get one from [`Fetch::abort_handle`](super::Fetch::abort_handle)
+/// or [`fetch_abortable`](super::fetch_abortable).
+///
+/// In the browser this aborts the underlying request through an `AbortController`, so the network
+/// request is actually cancelled rather than ignored.
+#[derive(Clone, Default)]
+pub struct AbortHandle(Rc<AbortState>);
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort the request. The fetch resolves to [`FetchError::Aborted`]. Aborting a request that
+    /// already finished does nothing.
+    pub fn abort(&self) {
+        self.abort_with(AbortReason::Aborted)
+    }
+
+    pub(crate) fn time_out(&self) {
+        self.abort_with(AbortReason::Timeout)
+    }
+
+    fn abort_with(&self, reason: AbortReason) {
+        if self.0.reason.get().is_some() {
+            return;
+        }
+        self.0.reason.set(Some(reason));
+        let callbacks = std::mem::take(&mut *self.0.callbacks.borrow_mut());
+        for callback in callbacks {
+            callback();
+        }
+        if let Some(waker) = self.0.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.reason.get().is_some()
+    }
+
+    pub fn signal(&self) -> AbortSignal {
+        AbortSignal(self.0.clone())
+    }
+}
+
+/// The receiving side of an [`AbortHandle`]. Backends use it to learn that the request they are
+/// working on has been aborted.
+#[derive(Clone)]
+pub struct AbortSignal(Rc<AbortState>);
+
+impl AbortSignal {
+    pub fn is_aborted(&self) -> bool {
+        self.0.reason.get().is_some()
+    }
+
+    /// Run `callback` when the request is aborted. If it has already been aborted, `callback`
+    /// runs immediately.
+    pub fn on_abort(&self, callback: impl FnOnce() + 'static) {
+        if self.is_aborted() {
+            callback();
+        } else {
+            self.0.callbacks.borrow_mut().push(Box::new(callback));
+        }
+    }
+
+    pub(crate) fn poll_aborted(&self, cx: &mut Context<'_>) -> Poll<FetchError> {
+        match self.0.reason.get() {
+            Some(AbortReason::Aborted) => Poll::Ready(FetchError::Aborted),
+            Some(AbortReason::Timeout) => Poll::Ready(FetchError::Timeout),
+            None => {
+                *self.0.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use futures::future::LocalBoxFuture;
+use futures::{FutureExt, Stream, StreamExt};
+use http::{Request, Response};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortController, ReadableStreamDefaultReader, RequestInit, RequestMode, ResponseType};
+use crate::global::fetch::{xhr, AbortSignal, Body, BodyStream, Direction, FetchError, Progress};
+use crate::global::fetch::error::js_message;
+
+/// Whatever actually performs the request. In the browser, this is [`BrowserBackend`], which uses
+/// `window.fetch`. Swap it out with [`set_backend`] to serve requests in-process, e.g. in native
+/// tests where there is no `window`.
+///
+/// Plain closures are backends too:
+///
+/// ```
+/// topaz::global::fetch::set_backend(|request: http::Request<topaz::global::fetch::Body>| {
+///     Ok(http::Response::new(b"mocked".to_vec()))
+/// });
+/// ```
+///
+/// Backends that can cancel work should watch [`RequestContext::signal`]. Whether they do or
+/// not, an aborted `fetch` resolves to an error immediately.
+pub trait Backend {
+    fn send(&self, request: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>>;
+
+    /// Like `send`, but resolves as soon as the headers arrive. Backends that can't stream
+    /// deliver the whole body as a single chunk.
+    fn send_streaming(&self, request: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<BodyStream>, FetchError>> {
+        let response = self.send(request, cx);
+        Box::pin(async move {
+            Ok(response.await?.map(BodyStream::from_bytes))
+        })
+    }
+}
+
+/// Where the end of the middleware chain leaves the body of a streaming response.
+pub(crate) type StreamSlot = Rc<RefCell<Option<BodyStream>>>;
+
+/// What a [`Backend`] needs to know about the `fetch` it is serving, besides the request itself.
+#[derive(Clone)]
+pub struct RequestContext {
+    signal: AbortSignal,
+    progress: Option<Rc<dyn Fn(Progress)>>,
+    stream: Option<StreamSlot>,
+}
+
+impl RequestContext {
+    pub fn new(signal: AbortSignal) -> Self {
+        RequestContext {
+            signal,
+            progress: None,
+            stream: None,
+        }
+    }
+
+    pub(crate) fn with_stream(mut self, stream: StreamSlot) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    pub(crate) fn stream(&self) -> Option<&StreamSlot> {
+        self.stream.as_ref()
+    }
+
+    /// Whether this is a [`fetch_stream`](super::fetch_stream). Middleware sees the status and
+    /// headers of streaming responses, but their body is empty, since it's read by the caller.
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub(crate) fn with_progress(mut self, progress: Option<Rc<dyn Fn(Progress)>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn signal(&self) -> &AbortSignal {
+        &self.signal
+    }
+
+    pub(crate) fn progress(&self) -> Option<Rc<dyn Fn(Progress)>> {
+        self.progress.clone()
+    }
+
+    /// Whether anyone is listening to [`report_progress`](Self::report_progress).
+    pub fn wants_progress(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    pub fn report_progress(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+}
+
+impl<F> Backend for F
+    where
+        F: Fn(Request<Body>) -> Result<Response<Vec<u8>>, FetchError>,
+{
+    fn send(&self, request: Request<Body>, _cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        Box::pin(futures::future::ready(self(request)))
+    }
+}
+
+/// The default backend. Translates the `http::Request` into a `web_sys::Request` and awaits
+/// `window.fetch`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserBackend;
+
+impl Backend for BrowserBackend {
+    fn send(&self, request: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        Box::pin(async move {
+            if cx.wants_progress() && !request.body().is_empty() {
+                return xhr::send(request, cx).await;
+            }
+            let (builder, response) = browser_fetch(request, cx.signal()).await?;
+            let body = if cx.wants_progress() {
+                let total = builder.headers_ref()
+                    .and_then(|headers| headers.get(http::header::CONTENT_LENGTH))
+                    .and_then(|length| length.to_str().ok()?.parse().ok());
+                read_with_progress(&response, total, &cx).await?
+            } else {
+                let buffer = JsFuture::from(response.array_buffer().map_err(decode)?).await
+                    .map_err(FetchError::from_js)?;
+                js_sys::Uint8Array::new(&buffer).to_vec()
+            };
+            builder.body(body)
+                .map_err(|e| FetchError::Decode(e.to_string()))
+        })
+    }
+
+    fn send_streaming(&self, request: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<BodyStream>, FetchError>> {
+        Box::pin(async move {
+            let (builder, response) = browser_fetch(request, cx.signal()).await?;
+            let body = match ReaderStream::new(&response)? {
+                Some(stream) => BodyStream::new(stream),
+                None => BodyStream::from_bytes(Vec::new()),
+            };
+            builder.body(body)
+                .map_err(|e| FetchError::Decode(e.to_string()))
+        })
+    }
+}
+
+/// Send the request and wait for the headers. The body is left for the caller to read.
+async fn browser_fetch(request: Request<Body>, signal: &AbortSignal) -> Result<(http::response::Builder, web_sys::Response), FetchError> {
+    if cfg!(not(target_arch = "wasm32")) {
+        return Err(FetchError::Network("BrowserBackend is only available on wasm32. Use set_backend to install a different backend.".to_string()));
+    }
+    let window = web_sys::window()
+        .ok_or_else(|| FetchError::Network("no global `window` exists".to_string()))?;
+
+    let (parts, body) = request.into_parts();
+    let init = RequestInit::new();
+    init.set_method(parts.method.as_str());
+    init.set_mode(RequestMode::Cors);
+
+    let controller = AbortController::new()
+        .map_err(invalid_request)?;
+    init.set_signal(Some(&controller.signal()));
+    signal.on_abort(move || controller.abort());
+
+    let headers = web_sys::Headers::new()
+        .map_err(invalid_request)?;
+    for (name, value) in parts.headers.iter() {
+        let value = value.to_str()
+            .map_err(|_| FetchError::InvalidRequest(format!("Header {} is not valid UTF-8", name)))?;
+        headers.append(name.as_str(), value)
+            .map_err(invalid_request)?;
+    }
+    init.set_headers(&headers);
+
+    if let Some(form) = body.form() {
+        let form_data = form.to_form_data().map_err(invalid_request)?;
+        init.set_body(&form_data);
+    } else if !body.is_empty() {
+        init.set_body(&js_sys::Uint8Array::from(body.as_bytes()));
+    }
+
+    let request = web_sys::Request::new_with_str_and_init(&parts.uri.to_string(), &init)
+        .map_err(invalid_request)?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_request(&request)).await
+        .map_err(FetchError::from_js)?
+        .unchecked_into();
+    if matches!(response.type_(), ResponseType::Opaque | ResponseType::Opaqueredirect) {
+        return Err(FetchError::Cors);
+    }
+
+    let mut builder = Response::builder()
+        .status(response.status());
+    let entries = js_sys::try_iter(&response.headers())
+        .ok()
+        .flatten()
+        .ok_or_else(|| FetchError::Decode("Response headers are not iterable".to_string()))?;
+    for entry in entries {
+        let entry: js_sys::Array = entry.map_err(decode)?.unchecked_into();
+        if let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string()) {
+            builder = builder.header(name, value);
+        }
+    }
+    Ok((builder, response))
+}
+
+async fn read_with_progress(response: &web_sys::Response, total: Option<u64>, cx: &RequestContext) -> Result<Vec<u8>, FetchError> {
+    let mut body = Vec::new();
+    let mut stream = match ReaderStream::new(response)? {
+        Some(stream) => stream,
+        None => return Ok(body),
+    };
+    while let Some(chunk) = stream.next().await {
+        body.extend(chunk?);
+        cx.report_progress(Progress {
+            direction: Direction::Download,
+            loaded: body.len() as u64,
+            total,
+        });
+    }
+    Ok(body)
+}
+
+/// Reads a `ReadableStream` one chunk at a time. A chunk is only requested when the stream is
+/// polled, which is what gives `BodyStream` its backpressure.
+struct ReaderStream {
+    reader: ReadableStreamDefaultReader,
+    pending: Option<JsFuture>,
+    done: bool,
+}
+
+impl ReaderStream {
+    fn new(response: &web_sys::Response) -> Result<Option<Self>, FetchError> {
+        let stream = match response.body() {
+            Some(stream) => stream,
+            None => return Ok(None),
+        };
+        Ok(Some(ReaderStream {
+            reader: ReadableStreamDefaultReader::new(&stream).map_err(decode)?,
+            pending: None,
+            done: false,
+        }))
+    }
+}
+
+impl Stream for ReaderStream {
+    type Item = Result<Vec<u8>, FetchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let this = &mut *self;
+        let pending = this.pending.get_or_insert_with(|| JsFuture::from(this.reader.read()));
+        let result = futures::ready!(pending.poll_unpin(cx));
+        this.pending = None;
+        let chunk = match result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                this.done = true;
+                return Poll::Ready(Some(Err(FetchError::from_js(e))));
+            }
+        };
+        let done = js_sys::Reflect::get(&chunk, &JsValue::from_str("done"))
+            .map(|done| done.is_truthy())
+            .unwrap_or(true);
+        if done {
+            this.done = true;
+            return Poll::Ready(None);
+        }
+        let value = js_sys::Reflect::get(&chunk, &JsValue::from_str("value")).map_err(decode)?;
+        Poll::Ready(Some(Ok(js_sys::Uint8Array::new(&value).to_vec())))
+    }
+}
+
+impl Drop for ReaderStream {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.reader.cancel();
+        }
+    }
+}
+
+fn invalid_request(value: JsValue) -> FetchError {
+    FetchError::InvalidRequest(js_message(&value))
+}
+
+fn decode(value: JsValue) -> FetchError {
+    FetchError::Decode(js_message(&value))
+}
+
+thread_local! {
+    static BACKEND: RefCell<Rc<dyn Backend>> = RefCell::new(Rc::new(BrowserBackend));
+}
+
+/// Replace the backend used by `fetch` on the current thread.
+pub fn set_backend(backend: impl Backend + 'static) {
+    BACKEND.with(|b| *b.borrow_mut() = Rc::new(backend));
+}
+
+pub(crate) fn current() -> Rc<dyn Backend> {
+    BACKEND.with(|b| b.borrow().clone())
+}
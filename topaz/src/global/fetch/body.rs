@@ -0,0 +1,96 @@
+use crate::global::fetch::Multipart;
+
+/// The body of an outgoing request. Anything that converts `Into<Body>` can be used as the `T` in
+/// the `http::Request<T>` passed to `fetch`.
+///
+/// A body can carry its own content type, which is used as the `Content-Type` header if the
+/// request doesn't already set one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Body {
+    bytes: Vec<u8>,
+    content_type: Option<String>,
+    /// A multipart body with blob parts, which only the browser can encode.
+    form: Option<Multipart>,
+}
+
+impl Body {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Body {
+            bytes: bytes.into(),
+            content_type: None,
+            form: None,
+        }
+    }
+
+    /// Sent as `FormData`, which gets its content type and boundary from the browser.
+    pub(crate) fn form_data(multipart: Multipart) -> Self {
+        Body {
+            form: Some(multipart),
+            ..Body::default()
+        }
+    }
+
+    pub(crate) fn form(&self) -> Option<&Multipart> {
+        self.form.as_ref()
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty() && self.form.is_none()
+    }
+}
+
+impl From<()> for Body {
+    fn from(_: ()) -> Self {
+        Body::empty()
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::new(bytes)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Self {
+        Body::new(bytes)
+    }
+}
+
+/// Text bodies get the same default content type the browser would give them.
+impl From<String> for Body {
+    fn from(s: String) -> Self {
+        Body::new(s).with_content_type("text/plain;charset=UTF-8")
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Self {
+        Body::from(s.to_string())
+    }
+}
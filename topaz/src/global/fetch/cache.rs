@@ -0,0 +1,495 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures::future::{LocalBoxFuture, Shared, WeakShared};
+use futures::FutureExt;
+use http::header::{HeaderName, HeaderValue, CACHE_CONTROL, VARY};
+use http::{HeaderMap, Method, Request, Response, StatusCode, Version};
+use crate::global::fetch::{AbortHandle, Body, FetchError, Middleware, Next, Progress, RequestContext};
+use crate::global::timer::now_millis;
+
+type Pending = LocalBoxFuture<'static, Result<Stored, FetchError>>;
+type ProgressCallback = Rc<dyn Fn(Progress)>;
+
+/// An in-memory cache for `GET` and `HEAD` requests. Opt in by adding it as middleware, and keep a
+/// clone around if you need to clear it later.
+///
+/// - Responses are cached for their `Cache-Control: max-age`, or `default_ttl` if they don't set
+///   one. `no-store` and `no-cache` responses aren't cached.
+/// - Entries are keyed on method, URL and the request headers named by the response's `Vary`.
+/// - A request with `Cache-Control: no-cache` skips the cache but still refreshes it, and one with
+///   `no-store` bypasses it entirely.
+/// - Concurrent identical requests share a single network request. It's only aborted once every
+///   `fetch` waiting on it has been dropped, aborted or timed out, and reports progress to all of
+///   them.
+/// - A successful `POST`, `PUT`, `PATCH` or `DELETE` evicts cached entries for the same URL.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use topaz::global::fetch::{add_middleware, Cache};
+///
+/// let cache = Cache::new(Duration::from_secs(60));
+/// add_middleware(cache.clone());
+/// // Later, e.g. after logging out:
+/// cache.clear();
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    default_ttl: Duration,
+    max_entries: Cell<usize>,
+    entries: RefCell<HashMap<String, Vec<Entry>>>,
+    in_flight: RefCell<HashMap<String, Flight>>,
+}
+
+/// A request shared by concurrent identical `fetch`es. It has its own [`AbortHandle`], so that
+/// one of them giving up doesn't abort it for the rest.
+#[derive(Clone)]
+struct Flight {
+    shared: WeakShared<Pending>,
+    handle: AbortHandle,
+    waiters: Rc<RefCell<Waiters>>,
+}
+
+/// The `fetch`es waiting on a [`Flight`], by id, with their progress callbacks.
+#[derive(Default)]
+struct Waiters {
+    next_id: usize,
+    progress: HashMap<usize, Option<ProgressCallback>>,
+}
+
+impl Flight {
+    fn wait(&self, shared: Shared<Pending>, progress: Option<ProgressCallback>) -> Waiter {
+        let mut waiters = self.waiters.borrow_mut();
+        let id = waiters.next_id;
+        waiters.next_id += 1;
+        waiters.progress.insert(id, progress);
+        Waiter {
+            shared,
+            id,
+            flight: self.clone(),
+            done: false,
+        }
+    }
+}
+
+/// One `fetch`'s share of a [`Flight`]. Dropping the last unfinished one aborts the request.
+struct Waiter {
+    shared: Shared<Pending>,
+    id: usize,
+    flight: Flight,
+    done: bool,
+}
+
+impl Future for Waiter {
+    type Output = Result<Stored, FetchError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = futures::ready!(self.shared.poll_unpin(cx));
+        self.done = true;
+        Poll::Ready(result)
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut waiters = self.flight.waiters.borrow_mut();
+        waiters.progress.remove(&self.id);
+        let abandoned = waiters.progress.is_empty() && !self.done;
+        drop(waiters);
+        if abandoned {
+            self.flight.handle.abort();
+        }
+    }
+}
+
+struct Entry {
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    expires_at: u64,
+    stored: Stored,
+}
+
+/// A response that can be handed out more than once.
+#[derive(Clone)]
+struct Stored {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Stored {
+    fn new(response: Response<Vec<u8>>) -> Self {
+        let (parts, body) = response.into_parts();
+        Stored {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    fn to_response(&self) -> Response<Vec<u8>> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+#[derive(Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = CacheControl::default();
+        for directive in headers.get_all(CACHE_CONTROL).iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+        {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => cc.max_age = seconds.trim_matches('"').parse().ok(),
+                None if directive == "no-store" => cc.no_store = true,
+                None if directive == "no-cache" => cc.no_cache = true,
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+impl Cache {
+    pub fn new(default_ttl: Duration) -> Self {
+        Cache {
+            inner: Rc::new(Inner {
+                default_ttl,
+                max_entries: Cell::new(256),
+                entries: RefCell::new(HashMap::new()),
+                in_flight: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Limit the number of cached responses. Expired entries are evicted first, then the ones
+    /// closest to expiring. Defaults to 256. Clones share the limit, like they share entries.
+    pub fn max_entries(self, max_entries: usize) -> Self {
+        self.inner.max_entries.set(max_entries);
+        self
+    }
+
+    pub fn clear(&self) {
+        self.inner.entries.borrow_mut().clear();
+    }
+
+    /// Evict cached responses for `url`, for every method.
+    pub fn invalidate(&self, url: &str) {
+        self.inner.entries.borrow_mut()
+            .retain(|key, _| key.split_once(' ').map(|(_, u)| u) != Some(url));
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.entries.borrow().values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lookup(&self, key: &str, request: &Request<Body>) -> Option<Response<Vec<u8>>> {
+        let now = now_millis();
+        let entries = self.inner.entries.borrow();
+        entries.get(key)?
+            .iter()
+            .filter(|entry| entry.expires_at > now)
+            .find(|entry| entry.vary.iter().all(|(name, value)| request.headers().get(name) == value.as_ref()))
+            .map(|entry| entry.stored.to_response())
+    }
+
+    fn store(&self, key: String, request_headers: &HeaderMap, stored: &Stored) {
+        if !stored.status.is_success() {
+            return;
+        }
+        let cc = CacheControl::parse(&stored.headers);
+        if cc.no_store || cc.no_cache {
+            return;
+        }
+        let mut vary = Vec::new();
+        for value in stored.headers.get_all(VARY).iter().filter_map(|v| v.to_str().ok()) {
+            for name in value.split(',').map(str::trim) {
+                if name == "*" {
+                    return;
+                }
+                if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                    let value = request_headers.get(&name).cloned();
+                    vary.push((name, value));
+                }
+            }
+        }
+        let ttl = cc.max_age.map(Duration::from_secs).unwrap_or(self.inner.default_ttl);
+        if ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.inner.entries.borrow_mut();
+        let variants = entries.entry(key).or_default();
+        variants.retain(|entry| entry.vary != vary);
+        variants.push(Entry {
+            vary,
+            expires_at: now_millis() + ttl.as_millis() as u64,
+            stored: stored.clone(),
+        });
+        evict(&mut entries, self.inner.max_entries.get());
+    }
+}
+
+fn evict(entries: &mut HashMap<String, Vec<Entry>>, max_entries: usize) {
+    let now = now_millis();
+    let mut count: usize = entries.values().map(Vec::len).sum();
+    if count > max_entries {
+        for variants in entries.values_mut() {
+            variants.retain(|entry| entry.expires_at > now);
+        }
+        count = entries.values().map(Vec::len).sum();
+    }
+    while count > max_entries {
+        let oldest = entries.iter()
+            .flat_map(|(key, variants)| variants.iter().enumerate().map(move |(i, entry)| (entry.expires_at, key, i)))
+            .min()
+            .map(|(_, key, i)| (key.clone(), i));
+        match oldest {
+            Some((key, i)) => {
+                entries.get_mut(&key).expect("key exists").remove(i);
+            }
+            None => break,
+        }
+        count -= 1;
+    }
+    entries.retain(|_, variants| !variants.is_empty());
+}
+
+/// Requests are only shared if every header matches, since the `Vary` of the response isn't
+/// known yet.
+fn in_flight_key(key: &str, headers: &HeaderMap) -> String {
+    let mut headers: Vec<_> = headers.iter()
+        .map(|(name, value)| format!("{}: {}", name, String::from_utf8_lossy(value.as_bytes())))
+        .collect();
+    headers.sort();
+    format!("{}\n{}", key, headers.join("\n"))
+}
+
+impl Middleware for Cache {
+    fn handle(&self, request: Request<Body>, next: Next) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        let key = format!("{} {}", request.method(), request.uri());
+        if !matches!(*request.method(), Method::GET | Method::HEAD) {
+            let cache = self.clone();
+            let url = request.uri().to_string();
+            return Box::pin(async move {
+                let response = next.run(request).await?;
+                if response.status().is_success() {
+                    cache.invalidate(&url);
+                }
+                Ok(response)
+            });
+        }
+
+        let cc = CacheControl::parse(request.headers());
+        // The body of a streaming response never passes through here, so there's nothing to store.
+        if cc.no_store || next.context().is_streaming() {
+            return next.run(request);
+        }
+        if !cc.no_cache {
+            if let Some(response) = self.lookup(&key, &request) {
+                return Box::pin(futures::future::ready(Ok(response)));
+            }
+        }
+
+        let flight_key = in_flight_key(&key, request.headers());
+        let progress = next.context().progress();
+        let existing = self.inner.in_flight.borrow().get(&flight_key)
+            .and_then(|flight| Some((flight.shared.upgrade()?, flight.clone())));
+        let waiter = match existing {
+            Some((shared, flight)) => flight.wait(shared, progress),
+            None => {
+                let handle = AbortHandle::new();
+                let waiters = Rc::new(RefCell::new(Waiters::default()));
+                let report_progress = {
+                    let waiters = waiters.clone();
+                    move |progress: Progress| {
+                        let callbacks: Vec<_> = waiters.borrow().progress.values().flatten().cloned().collect();
+                        for callback in callbacks {
+                            callback(progress);
+                        }
+                    }
+                };
+                let cx = RequestContext::new(handle.signal()).with_progress(Some(Rc::new(report_progress)));
+                let next = next.with_context(cx);
+                let cache = self.clone();
+                let pending_key = flight_key.clone();
+                let request_headers = request.headers().clone();
+                let shared = async move {
+                    let result = next.run(request).await.map(Stored::new);
+                    cache.inner.in_flight.borrow_mut().remove(&pending_key);
+                    if let Ok(stored) = &result {
+                        cache.store(key, &request_headers, stored);
+                    }
+                    result
+                }.boxed_local().shared();
+                let flight = Flight {
+                    shared: shared.downgrade().expect("future has not completed"),
+                    handle,
+                    waiters,
+                };
+                self.inner.in_flight.borrow_mut().insert(flight_key, flight.clone());
+                flight.wait(shared, progress)
+            }
+        };
+        Box::pin(async move {
+            waiter.await.map(|stored| stored.to_response())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use futures::executor::block_on;
+    use crate::global::fetch::{add_middleware, clear_middleware, fetch, set_backend, Backend, RequestContext};
+
+    fn counting_backend(cache_control: &'static str) -> Rc<Cell<u32>> {
+        let hits = Rc::new(Cell::new(0));
+        set_backend({
+            let hits = hits.clone();
+            move |request: Request<Body>| {
+                hits.set(hits.get() + 1);
+                let lang = request.headers().get("accept-language").cloned();
+                Ok(Response::builder()
+                    .header(CACHE_CONTROL, cache_control)
+                    .header(VARY, "Accept-Language")
+                    .body(lang.map(|l| l.as_bytes().to_vec()).unwrap_or_default())?)
+            }
+        });
+        hits
+    }
+
+    fn get(url: &str, lang: &str) -> Response<Vec<u8>> {
+        let request = Request::get(url).header("accept-language", lang).body(()).unwrap();
+        block_on(fetch(request)).unwrap()
+    }
+
+    #[test]
+    fn test_cache_hit_and_vary() {
+        let hits = counting_backend("max-age=60");
+        clear_middleware();
+        let cache = Cache::new(Duration::from_secs(60));
+        add_middleware(cache.clone());
+
+        assert_eq!(get("/a", "en").body(), b"en");
+        assert_eq!(get("/a", "en").body(), b"en");
+        assert_eq!(hits.get(), 1);
+        assert_eq!(get("/a", "fr").body(), b"fr");
+        assert_eq!(hits.get(), 2);
+        assert_eq!(cache.len(), 2);
+
+        block_on(fetch(Request::post("/a").body(()).unwrap())).unwrap();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cache_no_store() {
+        let hits = counting_backend("no-store");
+        clear_middleware();
+        add_middleware(Cache::new(Duration::from_secs(60)));
+        get("/a", "en");
+        get("/a", "en");
+        assert_eq!(hits.get(), 2);
+    }
+
+    #[test]
+    fn test_cache_max_entries_after_clone() {
+        counting_backend("max-age=60");
+        clear_middleware();
+        let cache = Cache::new(Duration::from_secs(60));
+        add_middleware(cache.clone());
+        let cache = cache.max_entries(1);
+        get("/a", "en");
+        get("/b", "en");
+        assert_eq!(cache.len(), 1);
+    }
+
+    /// Hangs until `release` is set, so concurrent requests overlap.
+    #[derive(Default)]
+    struct Gated {
+        hits: Rc<Cell<u32>>,
+        release: Rc<Cell<bool>>,
+        aborted: Rc<Cell<bool>>,
+    }
+
+    impl Backend for Gated {
+        fn send(&self, _: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+            self.hits.set(self.hits.get() + 1);
+            let aborted = self.aborted.clone();
+            cx.signal().on_abort(move || aborted.set(true));
+            let release = self.release.clone();
+            Box::pin(futures::future::poll_fn(move |_| match release.get() {
+                true => std::task::Poll::Ready(Ok(Response::new(b"ok".to_vec()))),
+                false => std::task::Poll::Pending,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_cache_dedup() {
+        let hits = Rc::new(Cell::new(0));
+        let release = Rc::new(Cell::new(false));
+        set_backend(Gated { hits: hits.clone(), release: release.clone(), ..Gated::default() });
+        clear_middleware();
+        add_middleware(Cache::new(Duration::ZERO));
+        let mut requests: Vec<_> = (0..3).map(|_| fetch(Request::get("/a").body(()).unwrap())).collect();
+        for request in &mut requests {
+            assert!(request.now_or_never().is_none());
+        }
+        assert_eq!(hits.get(), 1);
+        release.set(true);
+        let responses = block_on(futures::future::join_all(requests));
+        assert!(responses.iter().all(|r| r.as_ref().unwrap().body() == b"ok"));
+    }
+
+    #[test]
+    fn test_cache_dedup_drop_first() {
+        let gated = Gated::default();
+        let (release, aborted) = (gated.release.clone(), gated.aborted.clone());
+        set_backend(gated);
+        clear_middleware();
+        add_middleware(Cache::new(Duration::ZERO));
+        let mut first = fetch(Request::get("/a").body(()).unwrap());
+        let mut second = fetch(Request::get("/a").body(()).unwrap());
+        assert!((&mut first).now_or_never().is_none());
+        assert!((&mut second).now_or_never().is_none());
+        drop(first);
+        assert!(!aborted.get());
+        release.set(true);
+        assert_eq!(block_on(second).unwrap().body(), b"ok");
+
+        release.set(false);
+        let mut third = fetch(Request::get("/a").body(()).unwrap());
+        assert!((&mut third).now_or_never().is_none());
+        drop(third);
+        assert!(aborted.get());
+    }
+}
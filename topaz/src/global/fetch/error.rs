@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+use http::StatusCode;
+use wasm_bindgen::JsValue;
+
+/// Everything that can go wrong with a `fetch`. Match on the variant to decide whether to retry,
+/// show a login screen, or give up.
+pub enum FetchError {
+    /// The request never got a response: the browser is offline, DNS failed, the connection was
+    /// reset, or the browser blocked the request. Browsers report a failed CORS preflight the same
+    /// way, so it ends up here too.
+    Network(String),
+    /// The browser returned an opaque response, so the status, headers and body are hidden.
+    Cors,
+    /// The server responded with a non-success status. Only produced by helpers that check the
+    /// status, such as [`ResponseExt::error_for_status`](super::ResponseExt::error_for_status)
+    /// and `fetch_json`.
+    Status {
+        status: StatusCode,
+        body: Vec<u8>,
+    },
+    /// The request didn't finish within its timeout.
+    Timeout,
+    /// The request was aborted before it finished.
+    Aborted,
+    /// The request couldn't be built, e.g. an invalid URI or a header value that isn't valid text.
+    InvalidRequest(String),
+    /// The response body couldn't be read or isn't in the expected encoding.
+    Decode(String),
+    /// A JSON request body could not be serialized, or a response body could not be deserialized.
+    Json(serde_json::Error),
+}
+
+impl FetchError {
+    /// The status code, if the server responded with one.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            FetchError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Map a rejected Javascript promise to a `FetchError`. `AbortError`s become
+    /// [`FetchError::Aborted`]; everything else is treated as a network failure.
+    pub(crate) fn from_js(value: JsValue) -> Self {
+        if js_property(&value, "name").as_deref() == Some("AbortError") {
+            return FetchError::Aborted;
+        }
+        FetchError::Network(js_message(&value))
+    }
+}
+
+pub(crate) fn js_message(value: &JsValue) -> String {
+    value.as_string()
+        .or_else(|| js_property(value, "message"))
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
+fn js_property(value: &JsValue, name: &str) -> Option<String> {
+    if !value.is_object() {
+        return None;
+    }
+    js_sys::Reflect::get(value, &JsValue::from_str(name)).ok()?.as_string()
+}
+
+/// `serde_json::Error` isn't `Clone`, so a cloned `Json` error keeps only the message.
+impl Clone for FetchError {
+    fn clone(&self) -> Self {
+        match self {
+            FetchError::Network(message) => FetchError::Network(message.clone()),
+            FetchError::Cors => FetchError::Cors,
+            FetchError::Status { status, body } => FetchError::Status { status: *status, body: body.clone() },
+            FetchError::Timeout => FetchError::Timeout,
+            FetchError::Aborted => FetchError::Aborted,
+            FetchError::InvalidRequest(message) => FetchError::InvalidRequest(message.clone()),
+            FetchError::Decode(message) => FetchError::Decode(message.clone()),
+            FetchError::Json(e) => FetchError::Json(serde::de::Error::custom(e)),
+        }
+    }
+}
+
+impl std::fmt::Debug for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FetchError: {}", self)
+    }
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Network(message) => write!(f, "Network error: {}", message),
+            FetchError::Cors => write!(f, "Opaque response, blocked by CORS"),
+            FetchError::Status { status, .. } => write!(f, "Request failed with status {}", status),
+            FetchError::Timeout => write!(f, "Request timed out"),
+            FetchError::Aborted => write!(f, "Request aborted"),
+            FetchError::InvalidRequest(message) => write!(f, "Invalid request: {}", message),
+            FetchError::Decode(message) => write!(f, "Failed to decode response body: {}", message),
+            FetchError::Json(e) => write!(f, "Invalid JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Json(e)
+    }
+}
+
+impl From<http::Error> for FetchError {
+    fn from(e: http::Error) -> Self {
+        FetchError::InvalidRequest(e.to_string())
+    }
+}
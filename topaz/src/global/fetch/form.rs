@@ -0,0 +1,256 @@
+use wasm_bindgen::JsValue;
+use crate::global::fetch::util::random;
+use crate::global::fetch::Body;
+use crate::global::urlencoded;
+
+/// An `application/x-www-form-urlencoded` body, the same format as a query string.
+///
+/// # Examples
+///
+/// ```
+/// use topaz::global::fetch::Form;
+///
+/// let form = Form::new()
+///     .append("username", "ferris")
+///     .append("password", "hunter2");
+/// let request = http::Request::post("/login").body(form).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Form {
+    pairs: Vec<(String, String)>,
+}
+
+impl Form {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn append(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pairs.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Form {
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> Self {
+        Form {
+            pairs: iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Form {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&urlencoded::serialize(self.pairs.iter().map(|(k, v)| (k, v))))
+    }
+}
+
+impl From<Form> for Body {
+    fn from(form: Form) -> Self {
+        Body::new(form.to_string()).with_content_type("application/x-www-form-urlencoded")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    data: PartData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PartData {
+    Bytes(Vec<u8>),
+    /// Left in JavaScript, so it's never copied into wasm memory.
+    Blob(web_sys::Blob),
+}
+
+impl Part {
+    pub fn text(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Part {
+            name: name.into(),
+            filename: None,
+            content_type: None,
+            data: PartData::Bytes(value.into().into_bytes()),
+        }
+    }
+
+    /// A file upload. `content_type` defaults to `application/octet-stream`.
+    pub fn file(name: impl Into<String>, filename: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: None,
+            data: PartData::Bytes(data.into()),
+        }
+    }
+
+    /// A file upload from a `Blob` or `File`, e.g. one picked with `<input type="file">`. The
+    /// browser reads it while sending, with the blob's own type as the content type.
+    ///
+    /// Only the browser can read a blob, so a [`Multipart`] with blob parts is sent as
+    /// `FormData` by [`BrowserBackend`](super::BrowserBackend). Other backends see an empty body.
+    pub fn blob(name: impl Into<String>, blob: &web_sys::Blob, filename: impl Into<String>) -> Self {
+        Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: None,
+            data: PartData::Blob(blob.clone()),
+        }
+    }
+
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+}
+
+/// A `multipart/form-data` body with text fields and file parts, the same as an HTML form with
+/// `enctype="multipart/form-data"`.
+///
+/// # Examples
+///
+/// ```
+/// use topaz::global::fetch::{Multipart, Part};
+///
+/// let form = Multipart::new()
+///     .text("title", "Holiday")
+///     .part(Part::file("photo", "beach.png", vec![0x89, 0x50, 0x4e, 0x47]).content_type("image/png"));
+/// let request = http::Request::post("/albums").body(form).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(Part::text(name, value))
+    }
+
+    pub fn file(self, name: impl Into<String>, filename: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.part(Part::file(name, filename, data))
+    }
+
+    pub fn part(mut self, part: Part) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    fn encode(&self, boundary: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in &self.parts {
+            out.extend(format!("--{}\r\n", boundary).as_bytes());
+            out.extend(format!("Content-Disposition: form-data; name=\"{}\"", escape(&part.name)).as_bytes());
+            if let Some(filename) = &part.filename {
+                out.extend(format!("; filename=\"{}\"", escape(filename)).as_bytes());
+                let content_type = part.content_type.as_deref().unwrap_or("application/octet-stream");
+                out.extend(format!("\r\nContent-Type: {}", content_type).as_bytes());
+            } else if let Some(content_type) = &part.content_type {
+                out.extend(format!("\r\nContent-Type: {}", content_type).as_bytes());
+            }
+            out.extend(b"\r\n\r\n");
+            if let PartData::Bytes(data) = &part.data {
+                out.extend(data);
+            }
+            out.extend(b"\r\n");
+        }
+        out.extend(format!("--{}--\r\n", boundary).as_bytes());
+        out
+    }
+
+    fn contains(&self, boundary: &str) -> bool {
+        self.parts.iter().any(|part| match &part.data {
+            PartData::Bytes(data) => data.windows(boundary.len()).any(|window| window == boundary.as_bytes()),
+            PartData::Blob(_) => false,
+        })
+    }
+
+    fn has_blobs(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part.data, PartData::Blob(_)))
+    }
+
+    /// The same fields as `FormData`, which the browser encodes with its own boundary.
+    pub(crate) fn to_form_data(&self) -> Result<web_sys::FormData, JsValue> {
+        let form_data = web_sys::FormData::new()?;
+        for part in &self.parts {
+            match (&part.data, &part.filename) {
+                (PartData::Blob(blob), Some(filename)) => form_data.append_with_blob_and_filename(&part.name, blob, filename)?,
+                (PartData::Blob(blob), None) => form_data.append_with_blob(&part.name, blob)?,
+                (PartData::Bytes(data), Some(filename)) => {
+                    let options = web_sys::BlobPropertyBag::new();
+                    options.set_type(part.content_type.as_deref().unwrap_or("application/octet-stream"));
+                    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data.as_slice()));
+                    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+                    form_data.append_with_blob_and_filename(&part.name, &blob, filename)?;
+                }
+                (PartData::Bytes(data), None) => form_data.append_with_str(&part.name, &String::from_utf8_lossy(data))?,
+            }
+        }
+        Ok(form_data)
+    }
+}
+
+/// Names and filenames are quoted, so quotes and newlines are escaped the way browsers do it.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn boundary() -> String {
+    let bits = (random() * (1u64 << 52) as f64) as u64;
+    format!("----TopazFormBoundary{:013x}", bits)
+}
+
+impl From<Multipart> for Body {
+    fn from(multipart: Multipart) -> Self {
+        if multipart.has_blobs() {
+            return Body::form_data(multipart);
+        }
+        let mut boundary = boundary();
+        while multipart.contains(&boundary) {
+            boundary = self::boundary();
+        }
+        Body::new(multipart.encode(&boundary))
+            .with_content_type(format!("multipart/form-data; boundary={}", boundary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form() {
+        let body = Body::from(Form::new().append("q", "a b&c=d").append("lang", "日本"));
+        assert_eq!(body.content_type(), Some("application/x-www-form-urlencoded"));
+        assert_eq!(body.as_bytes(), b"q=a+b%26c%3Dd&lang=%E6%97%A5%E6%9C%AC");
+    }
+
+    #[test]
+    fn test_multipart() {
+        let multipart = Multipart::new()
+            .text("title", "Hi")
+            .part(Part::file("doc", "a\"b.txt", "hello").content_type("text/plain"));
+        assert_eq!(String::from_utf8(multipart.encode("XYZ")).unwrap(), "\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\
+            \r\n\
+            Hi\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"doc\"; filename=\"a%22b.txt\"\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            hello\r\n\
+            --XYZ--\r\n");
+
+        let body = Body::from(multipart);
+        let content_type = body.content_type().unwrap();
+        let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        assert!(body.as_bytes().starts_with(format!("--{}\r\n", boundary).as_bytes()));
+    }
+}
@@ -0,0 +1,136 @@
+use http::{header, Method, Request, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::global::fetch::{fetch, Body, FetchError};
+
+const APPLICATION_JSON: &str = "application/json";
+
+impl Body {
+    /// Serialize `value` as JSON. The body's content type is `application/json`.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Result<Self, FetchError> {
+        Ok(Body::new(serde_json::to_vec(value)?).with_content_type(APPLICATION_JSON))
+    }
+}
+
+/// Convenience methods for reading the body of a response returned by `fetch`.
+pub trait ResponseExt {
+    /// Deserialize the body as JSON.
+    fn json<T: DeserializeOwned>(&self) -> Result<T, FetchError>;
+
+    /// The body as UTF-8 text.
+    fn text(&self) -> Result<String, FetchError>;
+
+    /// Turn a response with a non-success status into a [`FetchError::Status`].
+    fn error_for_status(self) -> Result<Self, FetchError> where Self: Sized;
+}
+
+impl ResponseExt for Response<Vec<u8>> {
+    fn json<T: DeserializeOwned>(&self) -> Result<T, FetchError> {
+        Ok(serde_json::from_slice(self.body())?)
+    }
+
+    fn text(&self) -> Result<String, FetchError> {
+        String::from_utf8(self.body().clone())
+            .map_err(|e| FetchError::Decode(e.to_string()))
+    }
+
+    fn error_for_status(self) -> Result<Self, FetchError> {
+        if self.status().is_success() {
+            Ok(self)
+        } else {
+            Err(FetchError::Status {
+                status: self.status(),
+                body: self.into_body(),
+            })
+        }
+    }
+}
+
+/// Send `body` as JSON and deserialize the JSON response. `Content-Type` and `Accept` are set
+/// for you. A non-success status is an error rather than an attempt to deserialize the error page.
+///
+/// # Examples
+///
+/// ```
+/// # #[derive(serde::Serialize)] struct NewUser { name: String }
+/// # #[derive(serde::Deserialize)] struct User { id: u64 }
+/// # async fn run() -> Result<(), topaz::global::fetch::FetchError> {
+/// let user: User = topaz::global::fetch::fetch_json(
+///     http::Method::POST,
+///     "/api/users",
+///     &NewUser { name: "Ferris".to_string() },
+/// ).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn fetch_json<Req, Resp>(method: Method, url: &str, body: &Req) -> Result<Resp, FetchError>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned,
+{
+    let request = Request::builder()
+        .method(method)
+        .uri(url)
+        .header(header::ACCEPT, APPLICATION_JSON)
+        .body(Body::json(body)?)?;
+    send_json(request).await
+}
+
+/// `GET` the url and deserialize the JSON response.
+pub async fn get_json<Resp: DeserializeOwned>(url: &str) -> Result<Resp, FetchError> {
+    let request = Request::get(url)
+        .header(header::ACCEPT, APPLICATION_JSON)
+        .body(Body::empty())?;
+    send_json(request).await
+}
+
+async fn send_json<Resp: DeserializeOwned>(request: Request<Body>) -> Result<Resp, FetchError> {
+    fetch(request).await?
+        .error_for_status()?
+        .json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use serde::Deserialize;
+    use crate::global::fetch::set_backend;
+
+    #[derive(Serialize)]
+    struct Search {
+        q: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Hit {
+        id: u32,
+    }
+
+    #[test]
+    fn test_fetch_json() {
+        set_backend(|request: Request<Body>| {
+            assert_eq!(request.headers()[header::CONTENT_TYPE], APPLICATION_JSON);
+            assert_eq!(request.headers()[header::ACCEPT], APPLICATION_JSON);
+            assert_eq!(request.body().as_bytes(), br#"{"q":"rust"}"#);
+            Ok(Response::new(br#"[{"id":1},{"id":2}]"#.to_vec()))
+        });
+        let hits: Vec<Hit> = block_on(fetch_json(Method::POST, "/search", &Search { q: "rust".to_string() })).unwrap();
+        assert_eq!(hits, vec![Hit { id: 1 }, Hit { id: 2 }]);
+    }
+
+    #[test]
+    fn test_fetch_json_invalid_response() {
+        set_backend(|_: Request<Body>| Ok(Response::new(b"<html>".to_vec())));
+        let err = block_on(get_json::<Vec<Hit>>("/search")).unwrap_err();
+        assert!(matches!(err, FetchError::Json(_)));
+    }
+
+    #[test]
+    fn test_fetch_json_error_status() {
+        set_backend(|_: Request<Body>| Ok(Response::builder().status(401).body(b"login".to_vec())?));
+        let err = block_on(get_json::<Vec<Hit>>("/search")).unwrap_err();
+        assert_eq!(err.status(), Some(http::StatusCode::UNAUTHORIZED));
+        assert!(matches!(err, FetchError::Status { body, .. } if body == b"login"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use futures::future::LocalBoxFuture;
+use http::{Request, Response};
+use crate::global::fetch::{backend, Backend, Body, FetchError, RequestContext};
+
+/// Wraps every `fetch` on the current thread. Middleware can rewrite the outgoing request, inspect
+/// the response, or call `next` more than once to replay the request, e.g. after refreshing an
+/// expired token.
+///
+/// Async closures taking `(Request<Body>, Next)` are middleware too:
+///
+/// ```
+/// use topaz::global::fetch::{add_middleware, Body, Next};
+///
+/// add_middleware(|mut request: http::Request<Body>, next: Next| async move {
+///     request.headers_mut().insert("x-correlation-id", "abc123".parse().unwrap());
+///     next.run(request).await
+/// });
+/// ```
+pub trait Middleware {
+    fn handle(&self, request: Request<Body>, next: Next) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>>;
+}
+
+impl<F, Fut> Middleware for F
+    where
+        F: Fn(Request<Body>, Next) -> Fut,
+        Fut: Future<Output=Result<Response<Vec<u8>>, FetchError>> + 'static,
+{
+    fn handle(&self, request: Request<Body>, next: Next) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        Box::pin(self(request, next))
+    }
+}
+
+/// The rest of the middleware chain, ending in the [`Backend`].
+#[derive(Clone)]
+pub struct Next {
+    chain: Rc<Vec<Rc<dyn Middleware>>>,
+    index: usize,
+    backend: Rc<dyn Backend>,
+    cx: RequestContext,
+}
+
+impl Next {
+    /// Pass the request on to the next middleware, or the backend if this is the last one.
+    pub fn run(&self, request: Request<Body>) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        match self.chain.get(self.index) {
+            Some(middleware) => middleware.handle(request, Next {
+                index: self.index + 1,
+                ..self.clone()
+            }),
+            None => match self.cx.stream() {
+                Some(slot) => {
+                    let response = self.backend.send_streaming(request, self.cx.clone());
+                    let slot = slot.clone();
+                    Box::pin(async move {
+                        let (parts, body) = response.await?.into_parts();
+                        *slot.borrow_mut() = Some(body);
+                        Ok(Response::from_parts(parts, Vec::new()))
+                    })
+                }
+                None => self.backend.send(request, self.cx.clone()),
+            },
+        }
+    }
+
+    /// The context of the `fetch` being handled, e.g. to check if it was aborted.
+    pub fn context(&self) -> &RequestContext {
+        &self.cx
+    }
+
+    /// The same chain, but running with a different context, e.g. for a request shared by more
+    /// than one `fetch`.
+    pub(crate) fn with_context(&self, cx: RequestContext) -> Next {
+        Next { cx, ..self.clone() }
+    }
+}
+
+thread_local! {
+    static CHAIN: RefCell<Rc<Vec<Rc<dyn Middleware>>>> = RefCell::new(Rc::new(Vec::new()));
+}
+
+/// Append `middleware` to the chain. Middleware runs in the order it was added, so the first one
+/// added sees the request first and the response last.
+///
+/// Apps usually register their middleware once, with `topaz::Builder::middleware`, which calls
+/// this from `start()`. Use this directly for middleware that comes and goes, e.g. in tests.
+pub fn add_middleware(middleware: impl Middleware + 'static) {
+    push(Rc::new(middleware));
+}
+
+pub(crate) fn push(middleware: Rc<dyn Middleware>) {
+    CHAIN.with(|chain| {
+        let mut chain = chain.borrow_mut();
+        let mut middlewares = chain.as_ref().clone();
+        middlewares.push(middleware);
+        *chain = Rc::new(middlewares);
+    });
+}
+
+/// Remove all middleware.
+pub fn clear_middleware() {
+    CHAIN.with(|chain| *chain.borrow_mut() = Rc::new(Vec::new()));
+}
+
+/// Copy a request so it can be sent again. `http::Request` isn't `Clone` because its extensions
+/// can't be cloned, so they are left out.
+pub fn clone_request(request: &Request<Body>) -> Request<Body> {
+    let mut clone = Request::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+    clone
+}
+
+pub(crate) fn run(request: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+    Next {
+        chain: CHAIN.with(|chain| chain.borrow().clone()),
+        index: 0,
+        backend: backend::current(),
+        cx,
+    }.run(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use futures::executor::block_on;
+    use http::StatusCode;
+    use crate::global::fetch::{fetch, fetch_stream, set_backend};
+
+    #[test]
+    fn test_middleware_order() {
+        set_backend(|request: Request<Body>| {
+            let trace = request.headers().get_all("x-trace").iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join(",");
+            Ok(Response::new(trace.into_bytes()))
+        });
+        clear_middleware();
+        add_middleware(|mut request: Request<Body>, next: Next| async move {
+            request.headers_mut().append("x-trace", "first".parse().unwrap());
+            next.run(request).await
+        });
+        add_middleware(|mut request: Request<Body>, next: Next| async move {
+            request.headers_mut().append("x-trace", "second".parse().unwrap());
+            next.run(request).await
+        });
+        let response = block_on(fetch(Request::get("/").body(()).unwrap())).unwrap();
+        assert_eq!(response.body(), b"first,second");
+    }
+
+    #[test]
+    fn test_middleware_replay() {
+        set_backend(|request: Request<Body>| {
+            let status = match request.headers().get("authorization") {
+                Some(token) if token == "fresh" => StatusCode::OK,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            Ok(Response::builder().status(status).body(Vec::new())?)
+        });
+        clear_middleware();
+        let refreshes = Rc::new(Cell::new(0));
+        add_middleware({
+            let refreshes = refreshes.clone();
+            move |mut request: Request<Body>, next: Next| {
+                let refreshes = refreshes.clone();
+                async move {
+                    request.headers_mut().insert("authorization", "stale".parse().unwrap());
+                    let response = next.run(clone_request(&request)).await?;
+                    if response.status() != StatusCode::UNAUTHORIZED {
+                        return Ok(response);
+                    }
+                    refreshes.set(refreshes.get() + 1);
+                    request.headers_mut().insert("authorization", "fresh".parse().unwrap());
+                    next.run(request).await
+                }
+            }
+        });
+        let response = block_on(fetch(Request::get("/me").body(()).unwrap())).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(refreshes.get(), 1);
+    }
+
+    #[test]
+    fn test_middleware_stream() {
+        use futures::StreamExt;
+        set_backend(|request: Request<Body>| {
+            let token = request.headers().get("authorization").unwrap().as_bytes().to_vec();
+            Ok(Response::new(token))
+        });
+        clear_middleware();
+        add_middleware(|mut request: Request<Body>, next: Next| async move {
+            assert!(next.context().is_streaming());
+            request.headers_mut().insert("authorization", "token".parse().unwrap());
+            let response = next.run(request).await?;
+            assert!(response.body().is_empty());
+            Ok(response)
+        });
+        let response = block_on(fetch_stream(Request::get("/feed").body(()).unwrap())).unwrap();
+        let body: Vec<Vec<u8>> = block_on(response.into_body().map(Result::unwrap).collect());
+        assert_eq!(body.concat(), b"token");
+    }
+}
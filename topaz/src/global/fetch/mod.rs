@@ -0,0 +1,363 @@
+mod abort;
+mod backend;
+mod body;
+mod cache;
+mod error;
+mod form;
+mod json;
+mod middleware;
+mod progress;
+mod retry;
+mod stream;
+mod util;
+mod xhr;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use http::{Request, Response};
+use crate::global::timer::{clear_timeout, set_timeout, TimeoutId};
+
+pub use abort::{AbortHandle, AbortSignal};
+pub use backend::{Backend, BrowserBackend, RequestContext, set_backend};
+pub use body::Body;
+pub use cache::Cache;
+pub use error::FetchError;
+pub use form::{Form, Multipart, Part};
+pub use json::{fetch_json, get_json, ResponseExt};
+pub use middleware::{add_middleware, clear_middleware, clone_request, Middleware, Next};
+pub(crate) use middleware::push as push_middleware;
+pub use progress::{Direction, Progress};
+pub use retry::RetryPolicy;
+pub use stream::{BodyStream, JsonLines, Lines};
+
+type SendFn<B> = fn(Request<Body>, RequestContext, Option<RetryPolicy>) -> LocalBoxFuture<'static, Result<Response<B>, FetchError>>;
+
+/// Send `request` through the [`Middleware`] chain and the installed [`Backend`], which is
+/// `window.fetch` unless replaced with [`set_backend`]. Any error response from the server is
+/// still `Ok`, same as in Javascript. Use [`ResponseExt::error_for_status`] to turn those into a
+/// [`FetchError::Status`].
+///
+/// The request is sent when the returned [`Fetch`] is first polled. Dropping it before it
+/// finishes aborts the request.
+///
+/// Inspiration: https://rustwasm.github.io/wasm-bindgen/examples/fetch.html
+///
+/// # Examples
+///
+/// ```
+/// # async fn run() -> Result<(), topaz::global::fetch::FetchError> {
+/// let request = http::Request::get("/api/users").body(()).unwrap();
+/// let response = topaz::global::fetch(request)
+///     .timeout(std::time::Duration::from_secs(10))
+///     .await?;
+/// println!("{}", String::from_utf8_lossy(response.body()));
+/// # Ok(())
+/// # }
+/// ```
+pub fn fetch<T: Into<Body>>(request: Request<T>) -> Fetch {
+    Fetch::new(request.map(Into::into), |request, cx, retry| match retry {
+        Some(policy) => Box::pin(policy.run(request, move |request| middleware::run(request, cx.clone()))),
+        None => middleware::run(request, cx),
+    })
+}
+
+/// Same as [`fetch`], but also returns a handle to abort the request, e.g. when the user types a
+/// new search term before the previous search finished.
+pub fn fetch_abortable<T: Into<Body>>(request: Request<T>) -> (Fetch, AbortHandle) {
+    let fetch = fetch(request);
+    let handle = fetch.abort_handle();
+    (fetch, handle)
+}
+
+/// Same as [`fetch`], but resolves as soon as the headers arrive and leaves the body to be read
+/// as a [`BodyStream`]. A timeout only covers waiting for the headers.
+///
+/// The request goes through the [`Middleware`] chain, so headers added by middleware are sent,
+/// but middleware sees the response with an empty body: the body is only read by the caller.
+/// [`RequestContext::is_streaming`] tells middleware which requests these are. Retries aren't
+/// supported, since a partially read stream can't be replayed.
+///
+/// # Examples
+///
+/// ```
+/// # #[derive(serde::Deserialize)] struct Event { id: u64 }
+/// # async fn run() -> Result<(), topaz::global::fetch::FetchError> {
+/// use futures::StreamExt;
+///
+/// let request = http::Request::get("/api/events").body(()).unwrap();
+/// let mut events = topaz::global::fetch::fetch_stream(request).await?
+///     .into_body()
+///     .json_lines::<Event>();
+/// while let Some(event) = events.next().await {
+///     println!("{}", event?.id);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn fetch_stream<T: Into<Body>>(request: Request<T>) -> Fetch<BodyStream> {
+    Fetch::new(request.map(Into::into), |request, cx, _| {
+        let slot = Rc::new(RefCell::new(None));
+        let response = middleware::run(request, cx.with_stream(slot.clone()));
+        Box::pin(async move {
+            let response = response.await?;
+            // Middleware may answer without reaching the backend, in which case its body is used.
+            let stream = slot.borrow_mut().take();
+            Ok(response.map(|body| stream.unwrap_or_else(|| BodyStream::from_bytes(body))))
+        })
+    })
+}
+
+fn prepare(mut request: Request<Body>) -> Result<Request<Body>, FetchError> {
+    if let Some(content_type) = request.body().content_type() {
+        if !request.headers().contains_key(http::header::CONTENT_TYPE) {
+            let value = http::HeaderValue::from_str(content_type)
+                .map_err(|_| FetchError::InvalidRequest(format!("Invalid content type: {}", content_type)))?;
+            request.headers_mut().insert(http::header::CONTENT_TYPE, value);
+        }
+    }
+    Ok(request)
+}
+
+/// The future returned by [`fetch`] and [`fetch_stream`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Fetch<B = Vec<u8>> {
+    request: Option<Result<Request<Body>, FetchError>>,
+    send: SendFn<B>,
+    timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    progress: Option<Rc<dyn Fn(Progress)>>,
+    handle: AbortHandle,
+    running: Option<LocalBoxFuture<'static, Result<Response<B>, FetchError>>>,
+    timer: Option<TimeoutId>,
+}
+
+impl<B> Fetch<B> {
+    fn new(request: Request<Body>, send: SendFn<B>) -> Self {
+        Fetch {
+            request: Some(prepare(request)),
+            send,
+            timeout: None,
+            retry: None,
+            progress: None,
+            handle: AbortHandle::new(),
+            running: None,
+            timer: None,
+        }
+    }
+
+    /// Fail with [`FetchError::Timeout`] if the response hasn't arrived after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.handle.clone()
+    }
+
+    fn finish(&mut self) {
+        self.running = None;
+        if let Some(timer) = self.timer.take() {
+            clear_timeout(timer);
+        }
+    }
+}
+
+impl Fetch {
+    /// Retry transient failures according to `policy`. The timeout covers all attempts, and every
+    /// attempt goes through the middleware chain again.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Call `callback` as the request body is sent and the response body is received.
+    ///
+    /// In the browser, `fetch` can't observe uploads, so a request with a body and a progress
+    /// callback is sent with `XMLHttpRequest` instead.
+    pub fn on_progress(mut self, callback: impl Fn(Progress) + 'static) -> Self {
+        self.progress = Some(Rc::new(callback));
+        self
+    }
+}
+
+impl<B> Future for Fetch<B> {
+    type Output = Result<Response<B>, FetchError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.running.is_none() {
+            let request = match this.request.take().expect("Fetch polled after completion") {
+                Ok(request) => request,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+            if let Some(timeout) = this.timeout {
+                let handle = this.handle.clone();
+                let millis = timeout.as_millis().min(i32::MAX as u128) as u32;
+                this.timer = Some(set_timeout(move || handle.time_out(), millis));
+            }
+            let cx = RequestContext::new(this.handle.signal())
+                .with_progress(this.progress.take());
+            this.running = Some((this.send)(request, cx, this.retry.take()));
+        }
+
+        if let Poll::Ready(e) = this.handle.signal().poll_aborted(cx) {
+            this.finish();
+            return Poll::Ready(Err(e));
+        }
+        let running = this.running.as_mut().expect("Fetch is running");
+        let result = futures::ready!(running.poll_unpin(cx));
+        this.finish();
+        Poll::Ready(result)
+    }
+}
+
+impl<B> Unpin for Fetch<B> {}
+
+impl<B> Drop for Fetch<B> {
+    fn drop(&mut self) {
+        if self.running.is_some() {
+            // Abort first, so the backend can still clean up while its future is alive.
+            self.handle.abort();
+            self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_mock_backend() {
+        let seen = Rc::new(RefCell::new(None));
+        set_backend({
+            let seen = seen.clone();
+            move |request: Request<Body>| {
+                *seen.borrow_mut() = Some((
+                    request.method().clone(),
+                    request.uri().to_string(),
+                    request.headers()["x-token"].clone(),
+                    request.headers()["content-type"].clone(),
+                    request.body().as_bytes().to_vec(),
+                ));
+                Ok(Response::builder()
+                    .status(201)
+                    .header("content-type", "text/plain")
+                    .body(b"created".to_vec())?)
+            }
+        });
+
+        let request = Request::post("https://example.com/items")
+            .header("x-token", "abc")
+            .body("hello")
+            .unwrap();
+        let response = block_on(fetch(request)).unwrap();
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(response.body(), b"created");
+        let (method, uri, token, content_type, body) = seen.borrow_mut().take().unwrap();
+        assert_eq!(method, http::Method::POST);
+        assert_eq!(uri, "https://example.com/items");
+        assert_eq!(token, "abc");
+        assert_eq!(body, b"hello");
+        assert_eq!(content_type, "text/plain;charset=UTF-8");
+    }
+
+    #[test]
+    fn test_mock_backend_error() {
+        set_backend(|_: Request<Body>| Err(FetchError::Network("offline".to_string())));
+        let request = Request::get("https://example.com").body(()).unwrap();
+        let err = block_on(fetch(request)).unwrap_err();
+        assert!(matches!(err, FetchError::Network(ref message) if message == "offline"));
+    }
+
+    /// Never responds, but records whether the request was aborted.
+    struct Hang(Rc<Cell<bool>>);
+
+    impl Backend for Hang {
+        fn send(&self, _: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+            let aborted = self.0.clone();
+            cx.signal().on_abort(move || aborted.set(true));
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    #[test]
+    fn test_abort() {
+        let aborted = Rc::new(Cell::new(false));
+        set_backend(Hang(aborted.clone()));
+        let (mut fetch, handle) = fetch_abortable(Request::get("/slow").body(()).unwrap());
+        assert!((&mut fetch).now_or_never().is_none());
+        handle.abort();
+        assert!(aborted.get());
+        assert!(matches!(fetch.now_or_never(), Some(Err(FetchError::Aborted))));
+    }
+
+    #[test]
+    fn test_drop_aborts() {
+        let aborted = Rc::new(Cell::new(false));
+        set_backend(Hang(aborted.clone()));
+        let mut fetch = fetch(Request::get("/slow").body(()).unwrap());
+        assert!((&mut fetch).now_or_never().is_none());
+        drop(fetch);
+        assert!(aborted.get());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let clock = crate::global::VirtualClock::new();
+        crate::global::set_clock(clock.clone());
+        let aborted = Rc::new(Cell::new(false));
+        set_backend(Hang(aborted.clone()));
+        let mut fetch = fetch(Request::get("/slow").body(()).unwrap()).timeout(Duration::from_secs(5));
+        assert!((&mut fetch).now_or_never().is_none());
+        clock.advance(Duration::from_secs(5));
+        assert!(aborted.get());
+        assert!(matches!(fetch.now_or_never(), Some(Err(FetchError::Timeout))));
+        assert!(clock.pending().is_empty());
+    }
+
+    #[test]
+    fn test_fetch_stream() {
+        use futures::StreamExt;
+        set_backend(|_: Request<Body>| Ok(Response::new(b"one\ntwo\n".to_vec())));
+        let response = block_on(fetch_stream(Request::get("/lines").body(()).unwrap())).unwrap();
+        let lines: Vec<String> = block_on(response.into_body().lines().map(Result::unwrap).collect());
+        assert_eq!(lines, vec!["one", "two"]);
+    }
+
+    struct Chunked;
+
+    impl Backend for Chunked {
+        fn send(&self, _: Request<Body>, cx: RequestContext) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+            for loaded in [4, 8] {
+                cx.report_progress(Progress { direction: Direction::Download, loaded, total: Some(8) });
+            }
+            Box::pin(futures::future::ready(Ok(Response::new(b"12345678".to_vec()))))
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        set_backend(Chunked);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let fetch = fetch(Request::get("/file").body(()).unwrap())
+            .on_progress({
+                let seen = seen.clone();
+                move |progress| seen.borrow_mut().push(progress.fraction().unwrap())
+            });
+        block_on(fetch).unwrap();
+        assert_eq!(*seen.borrow(), vec![0.5, 1.0]);
+    }
+}
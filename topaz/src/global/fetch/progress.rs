@@ -0,0 +1,26 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// Reported to the callback given to [`Fetch::on_progress`](super::Fetch::on_progress).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub direction: Direction,
+    /// Bytes sent or received so far.
+    pub loaded: u64,
+    /// Total bytes, if known. Downloads only know this if the server sent a `Content-Length`.
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// `loaded / total` between 0 and 1, if the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.loaded as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
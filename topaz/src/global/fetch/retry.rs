@@ -0,0 +1,210 @@
+use std::time::Duration;
+use futures::future::LocalBoxFuture;
+use http::{header, Method, Request, Response, StatusCode};
+use crate::global::fetch::{clone_request, Body, FetchError, Middleware, Next};
+use crate::global::fetch::util::random;
+use crate::global::timer::{now_millis, sleep};
+
+/// When and how often to retry a failed request. Delays grow exponentially from `base_delay`, are
+/// capped at `max_delay`, and are randomly shortened by up to `jitter` (a fraction between 0 and
+/// 1) so that clients don't retry in lockstep.
+///
+/// Apply it to a single request with [`Fetch::retry`](super::Fetch::retry), or to every request
+/// with `add_middleware(RetryPolicy::default())`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+    /// Only requests with these methods are retried. Defaults to the idempotent methods.
+    pub methods: Vec<Method>,
+    /// Responses with these statuses are retried. Network errors are always retried.
+    pub statuses: Vec<StatusCode>,
+    /// Wait for as long as the server's `Retry-After` header asks. If that is longer than
+    /// `max_delay`, the response is returned instead of retrying.
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            methods: vec![Method::GET, Method::HEAD, Method::OPTIONS, Method::PUT, Method::DELETE, Method::TRACE],
+            statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before attempt number `attempt + 1`, where `attempt` starts at 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random();
+        delay.mul_f64(1.0 - jitter)
+    }
+
+    /// How long to wait before retrying, or `None` if the result should be returned as is.
+    fn delay(&self, attempt: u32, result: &Result<Response<Vec<u8>>, FetchError>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let response = match result {
+            Err(FetchError::Network(_)) => return Some(self.backoff(attempt)),
+            Err(_) => return None,
+            Ok(response) if self.statuses.contains(&response.status()) => response,
+            Ok(_) => return None,
+        };
+        match retry_after(response).filter(|_| self.respect_retry_after) {
+            Some(wait) if wait > self.max_delay => None,
+            Some(wait) => Some(wait),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    pub(crate) async fn run(self, request: Request<Body>, next: impl Fn(Request<Body>) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>>) -> Result<Response<Vec<u8>>, FetchError> {
+        if !self.methods.contains(request.method()) {
+            return next(request).await;
+        }
+        let mut attempt = 1;
+        loop {
+            let result = next(clone_request(&request)).await;
+            match self.delay(attempt, &result) {
+                Some(delay) => sleep(delay).await,
+                None => return result,
+            }
+            attempt += 1;
+        }
+    }
+}
+
+impl Middleware for RetryPolicy {
+    fn handle(&self, request: Request<Body>, next: Next) -> LocalBoxFuture<'static, Result<Response<Vec<u8>>, FetchError>> {
+        Box::pin(self.clone().run(request, move |request| next.run(request)))
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response<Vec<u8>>) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = parse_http_date(value)?;
+    Some(Duration::from_millis(at.saturating_sub(now_millis())))
+}
+
+/// Parse an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT` into milliseconds since the epoch.
+fn parse_http_date(s: &str) -> Option<u64> {
+    let mut parts = s.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"]
+        .iter()
+        .position(|m| *m == month)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" {
+        return None;
+    }
+    // Days since the epoch, from Howard Hinnant's `days_from_civil`.
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146097 + doe).checked_sub(719468)?;
+    Some((((days * 24 + hours) * 60 + minutes) * 60 + seconds) * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use futures::executor::block_on;
+    use crate::global::fetch::{fetch, set_backend};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_status() {
+        let attempts = Rc::new(Cell::new(0));
+        set_backend({
+            let attempts = attempts.clone();
+            move |_: Request<Body>| {
+                attempts.set(attempts.get() + 1);
+                let status = if attempts.get() < 3 { 503 } else { 200 };
+                Ok(Response::builder().status(status).body(Vec::new())?)
+            }
+        });
+        let response = block_on(fetch(Request::get("/").body(()).unwrap()).retry(policy())).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let attempts = Rc::new(Cell::new(0));
+        set_backend({
+            let attempts = attempts.clone();
+            move |_: Request<Body>| {
+                attempts.set(attempts.get() + 1);
+                Err(FetchError::Network("offline".to_string()))
+            }
+        });
+        let result = block_on(fetch(Request::get("/").body(()).unwrap()).retry(policy()));
+        assert!(matches!(result, Err(FetchError::Network(_))));
+        assert_eq!(attempts.get(), 3);
+
+        attempts.set(0);
+        let result = block_on(fetch(Request::post("/").body(()).unwrap()).retry(policy()));
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1, "POST is not idempotent");
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_millis(1000));
+        let jittered = RetryPolicy { jitter: 1.0, ..policy }.backoff(2);
+        assert!(jittered <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let response = Response::builder()
+            .header(header::RETRY_AFTER, "120")
+            .body(Vec::new())
+            .unwrap();
+        assert_eq!(retry_after(&response), Some(Duration::from_secs(120)));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777000));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::stream::LocalBoxStream;
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use crate::global::fetch::FetchError;
+
+/// A response body that arrives in chunks, returned by
+/// [`fetch_stream`](super::fetch_stream). Chunks are only read from the network as the stream is
+/// polled, so a slow consumer slows down the download instead of buffering it in memory.
+///
+/// Dropping the stream cancels the rest of the download.
+pub struct BodyStream {
+    inner: LocalBoxStream<'static, Result<Vec<u8>, FetchError>>,
+}
+
+impl BodyStream {
+    pub fn new(stream: impl Stream<Item=Result<Vec<u8>, FetchError>> + 'static) -> Self {
+        BodyStream {
+            inner: stream.boxed_local(),
+        }
+    }
+
+    /// A stream with a single chunk, for backends that don't stream.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let chunk = if bytes.is_empty() { None } else { Some(Ok(bytes)) };
+        BodyStream::new(futures::stream::iter(chunk))
+    }
+
+    /// Buffer the rest of the body.
+    pub async fn bytes(mut self) -> Result<Vec<u8>, FetchError> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.next().await {
+            bytes.extend(chunk?);
+        }
+        Ok(bytes)
+    }
+
+    /// Split the body into lines, without the trailing `\n` or `\r\n`.
+    pub fn lines(self) -> Lines {
+        Lines {
+            inner: self,
+            buffer: Vec::new(),
+            scanned: 0,
+            done: false,
+        }
+    }
+
+    /// Decode newline-delimited JSON, one value per line. Blank lines are skipped.
+    pub fn json_lines<T: DeserializeOwned>(self) -> JsonLines<T> {
+        JsonLines {
+            inner: self.lines(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Vec<u8>, FetchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// Stream of lines returned by [`BodyStream::lines`].
+pub struct Lines {
+    inner: BodyStream,
+    buffer: Vec<u8>,
+    /// How much of `buffer` is known not to contain a newline, so it isn't searched again when
+    /// the next chunk arrives.
+    scanned: usize,
+    done: bool,
+}
+
+impl Lines {
+    fn take_line(&mut self) -> Option<Result<String, FetchError>> {
+        let end = match self.buffer[self.scanned..].iter().position(|b| *b == b'\n') {
+            Some(i) => self.scanned + i + 1,
+            None if self.done && !self.buffer.is_empty() => self.buffer.len(),
+            None => {
+                self.scanned = self.buffer.len();
+                return None;
+            }
+        };
+        let mut line: Vec<u8> = self.buffer.drain(..end).collect();
+        self.scanned = 0;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Some(String::from_utf8(line).map_err(|e| FetchError::Decode(e.to_string())))
+    }
+}
+
+impl Stream for Lines {
+    type Item = Result<String, FetchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                return Poll::Ready(Some(line));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match futures::ready!(self.inner.poll_next_unpin(cx)) {
+                Some(Ok(chunk)) => self.buffer.extend(chunk),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+/// Stream of values returned by [`BodyStream::json_lines`].
+pub struct JsonLines<T> {
+    inner: Lines,
+    _marker: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Stream for JsonLines<T> {
+    type Item = Result<T, FetchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let line = match futures::ready!(self.inner.poll_next_unpin(cx)) {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+            if !line.trim().is_empty() {
+                return Poll::Ready(Some(serde_json::from_str(&line).map_err(FetchError::from)));
+            }
+        }
+    }
+}
+
+impl<T> Unpin for JsonLines<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use serde::Deserialize;
+
+    fn chunks(chunks: &[&str]) -> BodyStream {
+        let chunks: Vec<_> = chunks.iter().map(|c| Ok(c.as_bytes().to_vec())).collect();
+        BodyStream::new(futures::stream::iter(chunks))
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines: Vec<String> = block_on(chunks(&["first\r\nsec", "ond\n\nla", "st"]).lines().map(Result::unwrap).collect());
+        assert_eq!(lines, vec!["first", "second", "", "last"]);
+        let lines: Vec<String> = block_on(chunks(&["a", "b\r", "\nc\n"]).lines().map(Result::unwrap).collect());
+        assert_eq!(lines, vec!["ab", "c"]);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Event {
+            n: u32,
+        }
+        let events: Vec<Event> = block_on(chunks(&["{\"n\":1}\n{\"n\"", ":2}\n\n"]).json_lines().map(Result::unwrap).collect());
+        assert_eq!(events, vec![Event { n: 1 }, Event { n: 2 }]);
+    }
+}
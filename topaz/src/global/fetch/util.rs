@@ -0,0 +1,21 @@
+use std::cell::Cell;
+use crate::global::timer::now_millis;
+
+/// A number in `[0, 1)`, for retry jitter and multipart boundaries. Neither needs to be
+/// unpredictable, so this isn't cryptographically secure.
+pub(crate) fn random() -> f64 {
+    if cfg!(target_arch = "wasm32") {
+        return js_sys::Math::random();
+    }
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(now_millis() | 1);
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
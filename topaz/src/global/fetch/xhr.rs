@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use futures::channel::oneshot;
+use http::{Request, Response};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::Closure;
+use web_sys::{EventTarget, ProgressEvent, XmlHttpRequest, XmlHttpRequestResponseType};
+use crate::global::fetch::{Body, Direction, FetchError, Progress, RequestContext};
+use crate::global::fetch::error::js_message;
+
+/// An event listener that is removed when dropped, so that a finished or aborted request never
+/// calls into a dropped closure.
+struct Listener {
+    target: EventTarget,
+    event_name: &'static str,
+    inner: Closure<dyn FnMut(ProgressEvent)>,
+}
+
+impl Listener {
+    fn new(target: &EventTarget, event_name: &'static str, callback: impl FnMut(ProgressEvent) + 'static) -> Result<Self, FetchError> {
+        let inner = Closure::wrap(Box::new(callback) as Box<dyn FnMut(ProgressEvent)>);
+        target.add_event_listener_with_callback(event_name, inner.as_ref().unchecked_ref())
+            .map_err(|e| FetchError::InvalidRequest(js_message(&e)))?;
+        Ok(Listener {
+            target: target.clone(),
+            event_name,
+            inner,
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            self.event_name,
+            self.inner.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+fn progress_listener(target: &EventTarget, direction: Direction, cx: &RequestContext) -> Result<Listener, FetchError> {
+    let cx = cx.clone();
+    Listener::new(target, "progress", move |event| {
+        cx.report_progress(Progress {
+            direction,
+            loaded: event.loaded() as u64,
+            total: event.length_computable().then(|| event.total() as u64),
+        });
+    })
+}
+
+/// `fetch` can't report upload progress, so requests with a body and a progress callback go
+/// through `XMLHttpRequest` instead.
+pub(crate) async fn send(request: Request<Body>, cx: RequestContext) -> Result<Response<Vec<u8>>, FetchError> {
+    let invalid_request = |e| FetchError::InvalidRequest(js_message(&e));
+    let xhr = XmlHttpRequest::new().map_err(invalid_request)?;
+    let (parts, body) = request.into_parts();
+    xhr.open_with_async(parts.method.as_str(), &parts.uri.to_string(), true)
+        .map_err(invalid_request)?;
+    xhr.set_response_type(XmlHttpRequestResponseType::Arraybuffer);
+    for (name, value) in parts.headers.iter() {
+        let value = value.to_str()
+            .map_err(|_| FetchError::InvalidRequest(format!("Header {} is not valid UTF-8", name)))?;
+        xhr.set_request_header(name.as_str(), value)
+            .map_err(invalid_request)?;
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let tx = Rc::new(RefCell::new(Some(tx)));
+    let settle = |result: fn() -> Result<(), FetchError>| {
+        let tx = tx.clone();
+        move |_: ProgressEvent| {
+            if let Some(tx) = tx.borrow_mut().take() {
+                let _ = tx.send(result());
+            }
+        }
+    };
+    let upload = xhr.upload().map_err(invalid_request)?;
+    let _listeners = [
+        progress_listener(&upload, Direction::Upload, &cx)?,
+        progress_listener(&xhr, Direction::Download, &cx)?,
+        Listener::new(&xhr, "load", settle(|| Ok(())))?,
+        Listener::new(&xhr, "error", settle(|| Err(FetchError::Network("XMLHttpRequest failed".to_string()))))?,
+        Listener::new(&xhr, "abort", settle(|| Err(FetchError::Aborted)))?,
+        Listener::new(&xhr, "timeout", settle(|| Err(FetchError::Timeout)))?,
+    ];
+    cx.signal().on_abort({
+        let xhr = xhr.clone();
+        move || {
+            let _ = xhr.abort();
+        }
+    });
+
+    match body.form() {
+        Some(form) => {
+            let form_data = form.to_form_data()
+                .map_err(|e| FetchError::InvalidRequest(js_message(&e)))?;
+            xhr.send_with_opt_form_data(Some(&form_data))
+        }
+        None => xhr.send_with_opt_u8_array(Some(body.as_bytes())),
+    }.map_err(|e| FetchError::Network(js_message(&e)))?;
+    rx.await.unwrap_or(Err(FetchError::Aborted))?;
+
+    let mut builder = Response::builder()
+        .status(xhr.status().map_err(|e| FetchError::Decode(js_message(&e)))?);
+    let headers = xhr.get_all_response_headers()
+        .map_err(|e| FetchError::Decode(js_message(&e)))?;
+    for line in headers.split("\r\n") {
+        if let Some((name, value)) = line.split_once(':') {
+            builder = builder.header(name.trim(), value.trim());
+        }
+    }
+    let buffer = xhr.response()
+        .map_err(|e| FetchError::Decode(js_message(&e)))?;
+    builder.body(js_sys::Uint8Array::new(&buffer).to_vec())
+        .map_err(|e| FetchError::Decode(e.to_string()))
+}
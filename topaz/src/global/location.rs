@@ -1,41 +1,70 @@
 use multimap::MultiMap;
+use serde::{Deserialize, Serialize};
+use crate::global::query::{self, QueryError};
+use crate::global::urlencoded;
 
 
-#[derive(Debug, Clone)]
+/// A parsed query string, as in `window.location.search`. Keys and values are decoded per
+/// `application/x-www-form-urlencoded`, so `?q=a%20b+c` has `q` set to `"a b c"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Query(MultiMap<String, String>);
 
 impl Query{
+    /// Parse a query string, with or without the leading `?`.
     pub fn from_string(s: &str) -> Self {
-        let mut query = MultiMap::new();
-        if s.len() > 0 {
-            for pair in s[1..].split('&').filter(|s| !s.is_empty()) {
-                let mut pair = pair.splitn(2, '=');
-                let key = pair.next().unwrap();
-                let value = pair.next().unwrap_or("");
-                query.insert(key.to_string(), value.to_string());
-            }
-        }
-        Self(query)
+        let s = s.strip_prefix('?').unwrap_or(s);
+        Self(urlencoded::parse(s).collect())
     }
 
+    /// Encode as a query string, with a leading `?` unless it's empty. Keys are sorted, and
+    /// repeated keys keep their values in order.
     pub fn to_string(&self) -> String {
-        let mut s = String::new();
         if self.0.is_empty() {
-            return s;
+            return String::new();
         }
-        s.push('?');
-        let mut first = true;
-        for (key, value) in self.0.iter() {
-            if first {
-                first = false;
-            } else {
-                s.push('&');
-            }
-            s.push_str(key);
-            s.push('=');
-            s.push_str(value);
-        }
-        s
+        let mut keys: Vec<&String> = self.0.keys().collect();
+        keys.sort();
+        let pairs = keys.into_iter()
+            .flat_map(|key| self.0.get_vec(key).into_iter().flatten().map(move |value| (key, value)));
+        format!("?{}", urlencoded::serialize(pairs))
+    }
+
+    /// Read the query into a struct, or any other `serde` type that deserializes from a map.
+    /// Fields are parsed from text, `Option` fields are `None` when missing or empty, and `Vec`
+    /// fields get every value of a repeated key. See the errors' [`QueryError::key`] for which
+    /// key failed.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use topaz::global::Query;
+    ///
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "lowercase")]
+    /// enum Sort { Newest, Oldest }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct SearchParams {
+    ///     q: String,
+    ///     page: Option<u32>,
+    ///     sort: Option<Sort>,
+    ///     #[serde(default)]
+    ///     tag: Vec<String>,
+    /// }
+    ///
+    /// let params: SearchParams = Query::from_string("?q=rust&tag=wasm&tag=web").parse().unwrap();
+    /// assert_eq!(params.tag, ["wasm", "web"]);
+    /// assert!(params.page.is_none());
+    /// ```
+    ///
+    /// A missing `Vec` field is an error unless it's marked `#[serde(default)]`.
+    pub fn parse<'a, T: Deserialize<'a>>(&'a self) -> Result<T, QueryError> {
+        query::deserialize(&self.0)
+    }
+
+    /// Build a query from a struct or map, the reverse of [`Query::parse`]. `None` fields are
+    /// left out, and sequences become repeated keys.
+    pub fn from_serializable<T: Serialize + ?Sized>(params: &T) -> Result<Self, QueryError> {
+        query::serialize(params).map(Self)
     }
 
 }
@@ -173,4 +202,50 @@ impl Drop for Location {
             self.inner.set_hash(&self.modifiable.anchor).unwrap();
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let query = Query::from_string("?q=a%20b+c&empty=&flag&tag=x&tag=y&snow=%E2%98%83");
+        assert_eq!(query["q".to_string()], "a b c");
+        assert_eq!(query["empty".to_string()], "");
+        assert_eq!(query["flag".to_string()], "");
+        assert_eq!(query.0.get_vec("tag").unwrap(), &["x", "y"]);
+        assert_eq!(query["snow".to_string()], "☃");
+    }
+
+    #[test]
+    fn test_decode_edge_cases() {
+        let query = Query::from_string("a=100%&b=%zz&c=%4&d=%FF&e=1=2&&f%3D=%26");
+        assert_eq!(query["a".to_string()], "100%");
+        assert_eq!(query["b".to_string()], "%zz");
+        assert_eq!(query["c".to_string()], "%4");
+        assert_eq!(query["d".to_string()], "\u{FFFD}");
+        assert_eq!(query["e".to_string()], "1=2");
+        assert_eq!(query["f=".to_string()], "&");
+        assert_eq!(Query::from_string(""), Query::from_string("?"));
+        assert_eq!(Query::from_string("?").to_string(), "");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut map = MultiMap::new();
+        for (key, value) in [("q", "a b&c=d"), ("q", "+%"), ("path", "/a/b?c#d"), ("", "empty key"), ("emoji", "🦀")] {
+            map.insert(key.to_string(), value.to_string());
+        }
+        let query = Query(map);
+        let encoded = query.to_string();
+        assert_eq!(encoded, "?=empty+key&emoji=%F0%9F%A6%80&path=%2Fa%2Fb%3Fc%23d&q=a+b%26c%3Dd&q=%2B%25");
+        assert_eq!(Query::from_string(&encoded), query);
+    }
+
+    #[test]
+    fn test_no_double_encoding() {
+        assert_eq!(Query::from_string("?q=a%20b").to_string(), "?q=a+b");
+        assert_eq!(Query::from_string("?q=a+b%2Bc").to_string(), "?q=a+b%2Bc");
+    }
+}
@@ -1,12 +1,24 @@
+mod animation_frame;
+mod callbacks;
+mod debounce;
 mod document;
 mod timer;
-mod fetch;
+pub mod fetch;
 mod window;
 mod history;
 mod location;
+mod query;
+mod urlencoded;
 
 pub use fetch::fetch;
 pub use document::document;
-pub use location::location;
+pub use location::{location, Query};
+pub use query::QueryError;
 pub use history::history;
-pub use timer::{set_interval, set_timeout, clear_timeout, clear_interval};
+pub use timer::{set_interval, set_timeout, clear_timeout, clear_interval, sleep, interval, Sleep, Interval};
+pub use timer::{set_timeout_guard, set_interval_guard, TimeoutGuard, IntervalGuard, TimeoutId, IntervalId};
+pub use timer::{set_clock, BrowserClock, Clock, PendingTimer, VirtualClock};
+pub use timer::{request_idle_callback, request_idle_callback_with_timeout, cancel_idle_callback, IdleCallbackId, IdleDeadline};
+pub(crate) use timer::now_millis;
+pub use animation_frame::{request_animation_frame, cancel_animation_frame, animation_loop, AnimationFrameId, AnimationLoop};
+pub use debounce::{debounce, throttle, Debounced, Throttled};
@@ -0,0 +1,765 @@
+//! Map query strings to and from `serde` types, for [`Query::parse`](super::Query::parse) and
+//! [`Query::from_serializable`](super::Query::from_serializable).
+//!
+//! Each field is one key. Values are parsed from text, so numbers, bools and unit enum variants
+//! work as well as strings. An `Option` field is `None` when its key is missing or empty, and a
+//! `Vec` field collects every value of a repeated key.
+
+use std::fmt::{self, Display, Formatter};
+use multimap::MultiMap;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+
+/// A query string didn't match the type it was parsed into, or a value can't be written as a
+/// query string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    key: Option<String>,
+    message: String,
+}
+
+impl QueryError {
+    /// The query parameter that failed, if the error is about a single one.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn with_key(mut self, key: &str) -> Self {
+        self.key.get_or_insert_with(|| key.to_string());
+        self
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "query parameter `{}`: {}", key, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl de::Error for QueryError {
+    fn custom<T: Display>(msg: T) -> Self {
+        QueryError { key: None, message: msg.to_string() }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        QueryError { key: Some(field.to_string()), message: "missing".to_string() }
+    }
+}
+
+impl ser::Error for QueryError {
+    fn custom<T: Display>(msg: T) -> Self {
+        QueryError { key: None, message: msg.to_string() }
+    }
+}
+
+fn unsupported(what: &str) -> QueryError {
+    de::Error::custom(format!("{} can't be represented in a query string", what))
+}
+
+pub(crate) fn deserialize<'de, T: de::Deserialize<'de>>(query: &'de MultiMap<String, String>) -> Result<T, QueryError> {
+    T::deserialize(QueryDeserializer { query })
+}
+
+pub(crate) fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<MultiMap<String, String>, QueryError> {
+    let mut query = MultiMap::new();
+    value.serialize(QuerySerializer { query: &mut query })?;
+    Ok(query)
+}
+
+/// The whole query, as a map from keys to all of their values.
+struct QueryDeserializer<'de> {
+    query: &'de MultiMap<String, String>,
+}
+
+impl<'de> de::Deserializer<'de> for QueryDeserializer<'de> {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_map(Entries { entries: self.query.iter_all(), value: None })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_some(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Entries<'de, I> {
+    entries: I,
+    value: Option<(&'de str, &'de [String])>,
+}
+
+impl<'de, I: Iterator<Item=(&'de String, &'de Vec<String>)>> MapAccess<'de> for Entries<'de, I> {
+    type Error = QueryError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, QueryError> {
+        match self.entries.next() {
+            Some((key, values)) => {
+                self.value = Some((key, values));
+                seed.deserialize(key.as_str().into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, QueryError> {
+        let (key, values) = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Values { values }).map_err(|e| e.with_key(key))
+    }
+}
+
+/// All the values of one key. Most types take the first one; sequences take them all.
+struct Values<'de> {
+    values: &'de [String],
+}
+
+impl<'de> Values<'de> {
+    fn first(&self) -> Text<'de> {
+        Text(self.values.first().map(String::as_str).unwrap_or(""))
+    }
+}
+
+macro_rules! forward_to_first {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+                self.first().$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Values<'de> {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        if self.values.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            self.first().deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_seq(de::value::SeqDeserializer::new(self.values.iter().map(|value| Text(value))))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, QueryError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, QueryError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        if self.values.len() > 1 {
+            visitor.visit_some(self)
+        } else {
+            self.first().deserialize_option(visitor)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, QueryError> {
+        self.first().deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, QueryError> {
+        self.first().deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, QueryError> {
+        self.first().deserialize_struct(name, fields, visitor)
+    }
+
+    forward_to_first! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_map
+        deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// One value, parsed from text into whatever type is asked for.
+#[derive(Clone, Copy)]
+struct Text<'de>(&'de str);
+
+impl<'de> IntoDeserializer<'de, QueryError> for Text<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse_number {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+                match self.0.parse() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(e) => Err(de::Error::custom(format!("invalid number {:?}: {}", self.0, e))),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Text<'de> {
+    type Error = QueryError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    /// Flags without a value, as in `?verbose`, are true.
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        match self.0 {
+            "" | "true" | "1" | "on" | "yes" => visitor.visit_bool(true),
+            "false" | "0" | "off" | "no" => visitor.visit_bool(false),
+            other => Err(de::Error::custom(format!("invalid bool {:?}", other))),
+        }
+    }
+
+    parse_number! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    /// An empty value counts as missing.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_seq(de::value::SeqDeserializer::new(std::iter::once(self)))
+    }
+
+    /// Only unit variants, named by the value.
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, QueryError> {
+        visitor.visit_enum(de::value::BorrowedStrDeserializer::new(self.0))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, QueryError> {
+        Err(unsupported("a nested map"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value, QueryError> {
+        Err(unsupported("a nested struct"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf tuple tuple_struct identifier ignored_any
+    }
+}
+
+/// Writes the fields of a struct, or the entries of a map, as keys.
+struct QuerySerializer<'a> {
+    query: &'a mut MultiMap<String, String>,
+}
+
+fn top_level() -> QueryError {
+    ser::Error::custom("only structs and maps can be written as a query string")
+}
+
+impl<'a> ser::Serializer for QuerySerializer<'a> {
+    type Ok = ();
+    type Error = QueryError;
+    type SerializeSeq = Impossible<(), QueryError>;
+    type SerializeTuple = Impossible<(), QueryError>;
+    type SerializeTupleStruct = Impossible<(), QueryError>;
+    type SerializeTupleVariant = Impossible<(), QueryError>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), QueryError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_i8(self, _v: i8) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_i16(self, _v: i16) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_i32(self, _v: i32) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_i64(self, _v: i64) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_u8(self, _v: u8) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_u16(self, _v: u16) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_u32(self, _v: u32) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_u64(self, _v: u64) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_f32(self, _v: f32) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_f64(self, _v: f64) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_char(self, _v: char) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_str(self, _v: &str) -> Result<(), QueryError> { Err(top_level()) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), QueryError> { Err(top_level()) }
+
+    fn serialize_none(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<(), QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<(), QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(top_level())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a>, QueryError> {
+        Ok(MapSerializer { query: self.query, key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, QueryError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(top_level())
+    }
+}
+
+impl ser::SerializeStruct for QuerySerializer<'_> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), QueryError> {
+        value.serialize(ValueSerializer { key, query: self.query }).map_err(|e| e.with_key(key))
+    }
+
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+struct MapSerializer<'a> {
+    query: &'a mut MultiMap<String, String>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), QueryError> {
+        match key.serialize(TextSerializer)? {
+            Some(key) => self.key = Some(key),
+            None => return Err(ser::Error::custom("map keys can't be empty")),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        value.serialize(ValueSerializer { key: &key, query: self.query }).map_err(|e| e.with_key(&key))
+    }
+
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+/// Writes one field: nothing for `None`, one value per element for sequences, and one value
+/// otherwise.
+struct ValueSerializer<'a> {
+    key: &'a str,
+    query: &'a mut MultiMap<String, String>,
+}
+
+impl ValueSerializer<'_> {
+    fn push(self, value: Option<String>) -> Result<(), QueryError> {
+        if let Some(value) = value {
+            self.query.insert(self.key.to_string(), value);
+        }
+        Ok(())
+    }
+}
+
+macro_rules! forward_to_text {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<(), QueryError> {
+                let value = TextSerializer.$method(v)?;
+                self.push(value)
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = ();
+    type Error = QueryError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Impossible<(), QueryError>;
+    type SerializeTupleVariant = Impossible<(), QueryError>;
+    type SerializeMap = Impossible<(), QueryError>;
+    type SerializeStruct = Impossible<(), QueryError>;
+    type SerializeStructVariant = Impossible<(), QueryError>;
+
+    forward_to_text! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+    }
+
+    fn serialize_none(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), QueryError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), QueryError> {
+        self.push(Some(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<(), QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, QueryError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, QueryError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, QueryError> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, QueryError> {
+        Err(unsupported("a nested map"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, QueryError> {
+        Err(unsupported("a nested struct"))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+impl ser::SerializeSeq for ValueSerializer<'_> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        if let Some(value) = value.serialize(TextSerializer)? {
+            self.query.insert(self.key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for ValueSerializer<'_> {
+    type Ok = ();
+    type Error = QueryError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), QueryError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), QueryError> {
+        Ok(())
+    }
+}
+
+/// Writes a single value as text, or `None` for a missing one.
+struct TextSerializer;
+
+macro_rules! to_text {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<Option<String>, QueryError> {
+                Ok(Some(v.to_string()))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for TextSerializer {
+    type Ok = Option<String>;
+    type Error = QueryError;
+    type SerializeSeq = Impossible<Option<String>, QueryError>;
+    type SerializeTuple = Impossible<Option<String>, QueryError>;
+    type SerializeTupleStruct = Impossible<Option<String>, QueryError>;
+    type SerializeTupleVariant = Impossible<Option<String>, QueryError>;
+    type SerializeMap = Impossible<Option<String>, QueryError>;
+    type SerializeStruct = Impossible<Option<String>, QueryError>;
+    type SerializeStructVariant = Impossible<Option<String>, QueryError>;
+
+    to_text! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<String>, QueryError> {
+        match std::str::from_utf8(v) {
+            Ok(s) => Ok(Some(s.to_string())),
+            Err(_) => Err(unsupported("bytes that aren't UTF-8")),
+        }
+    }
+
+    fn serialize_none(self) -> Result<Option<String>, QueryError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<String>, QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<String>, QueryError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<String>, QueryError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Option<String>, QueryError> {
+        Ok(Some(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Option<String>, QueryError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<Option<String>, QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, QueryError> {
+        Err(unsupported("a nested sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, QueryError> {
+        Err(unsupported("a nested tuple"))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, QueryError> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, QueryError> {
+        Err(unsupported("a nested map"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, QueryError> {
+        Err(unsupported("a nested struct"))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, QueryError> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use crate::global::Query;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Sort {
+        Newest,
+        Oldest,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct SearchParams {
+        q: String,
+        page: Option<u32>,
+        sort: Option<Sort>,
+        #[serde(default)]
+        tag: Vec<String>,
+        #[serde(default)]
+        exact: bool,
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::from_string("?q=rust+wasm&page=2&sort=oldest&tag=a&tag=b%26c&exact");
+        let params: SearchParams = query.parse().unwrap();
+        assert_eq!(params, SearchParams {
+            q: "rust wasm".to_string(),
+            page: Some(2),
+            sort: Some(Sort::Oldest),
+            tag: vec!["a".to_string(), "b&c".to_string()],
+            exact: true,
+        });
+
+        let params: SearchParams = Query::from_string("?q=&page=&tag=one").parse().unwrap();
+        assert_eq!(params, SearchParams {
+            q: String::new(),
+            page: None,
+            sort: None,
+            tag: vec!["one".to_string()],
+            exact: false,
+        });
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = Query::from_string("?q=x&page=two").parse::<SearchParams>().unwrap_err();
+        assert_eq!(error.key(), Some("page"));
+        assert_eq!(error.to_string(), "query parameter `page`: invalid number \"two\": invalid digit found in string");
+
+        let error = Query::from_string("?q=x&sort=best").parse::<SearchParams>().unwrap_err();
+        assert_eq!(error.key(), Some("sort"));
+
+        let error = Query::from_string("?page=1").parse::<SearchParams>().unwrap_err();
+        assert_eq!(error.key(), Some("q"));
+        assert_eq!(error.to_string(), "query parameter `q`: missing");
+    }
+
+    #[test]
+    fn test_from_serializable() {
+        let params = SearchParams {
+            q: "a b".to_string(),
+            page: None,
+            sort: Some(Sort::Newest),
+            tag: vec!["x".to_string(), "y".to_string()],
+            exact: false,
+        };
+        let query = Query::from_serializable(&params).unwrap();
+        assert_eq!(query.to_string(), "?exact=false&q=a+b&sort=newest&tag=x&tag=y");
+        assert_eq!(query.parse::<SearchParams>().unwrap(), params);
+
+        let error = Query::from_serializable(&"not a struct").unwrap_err();
+        assert_eq!(error.key(), None);
+
+        #[derive(Serialize)]
+        struct Nested { inner: SearchParams }
+        let error = Query::from_serializable(&Nested { inner: params }).unwrap_err();
+        assert_eq!(error.key(), Some("inner"));
+    }
+}
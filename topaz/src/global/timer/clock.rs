@@ -0,0 +1,109 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::Closure;
+use crate::global::callbacks::{self, Registry};
+use crate::global::timer::VirtualClock;
+use crate::global::window::web_sys_window;
+
+/// Whatever actually schedules timers. In the browser, this is [`BrowserClock`], which uses
+/// `window.setTimeout`. Swap it out with [`set_clock`], e.g. for a
+/// [`VirtualClock`](super::VirtualClock) in native tests where there is no `window`.
+///
+/// Handles are opaque to the timer functions. Like in the browser, timeouts and intervals may
+/// share one pool of handles, since clearing is by handle.
+pub trait Clock {
+    /// Milliseconds since the Unix epoch, like `Date.now()`.
+    fn now(&self) -> u64;
+    fn set_timeout(&self, callback: Box<dyn FnOnce()>, millis: u32) -> u32;
+    fn clear_timeout(&self, handle: u32);
+    fn set_interval(&self, callback: Box<dyn FnMut()>, millis: u32) -> u32;
+    fn clear_interval(&self, handle: u32);
+}
+
+/// The default [`Clock`], backed by the browser's timers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserClock;
+
+thread_local! {
+    static TIMEOUTS: Registry = RefCell::new(HashMap::new());
+    /// Intervals run until cleared, so their closures stay here until then.
+    static INTERVALS: RefCell<HashMap<u32, Closure<dyn FnMut()>>> = RefCell::new(HashMap::new());
+}
+
+impl Clock for BrowserClock {
+    fn now(&self) -> u64 {
+        if cfg!(target_arch = "wasm32") {
+            js_sys::Date::now() as u64
+        } else {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0)
+        }
+    }
+
+    fn set_timeout(&self, callback: Box<dyn FnOnce()>, millis: u32) -> u32 {
+        callbacks::schedule(&TIMEOUTS, move |_| callback(), |function| {
+            web_sys_window()
+                .set_timeout_with_callback_and_timeout_and_arguments_0(function, millis as i32)
+                .expect("set_timeout failed") as u32
+        })
+    }
+
+    fn clear_timeout(&self, handle: u32) {
+        web_sys_window().clear_timeout_with_handle(handle as i32);
+        callbacks::release(&TIMEOUTS, handle);
+    }
+
+    fn set_interval(&self, mut callback: Box<dyn FnMut()>, millis: u32) -> u32 {
+        let a = Closure::wrap(Box::new(move || {
+            callback();
+            crate::print::flush_captured();
+        }) as Box<dyn FnMut()>);
+        let window = web_sys_window();
+        let interval = window
+            .set_interval_with_callback_and_timeout_and_arguments_0(a.as_ref().unchecked_ref(), millis as i32)
+            .expect("set_interval failed") as u32;
+        INTERVALS.with(|i| i.borrow_mut().insert(interval, a));
+        interval
+    }
+
+    fn clear_interval(&self, handle: u32) {
+        web_sys_window().clear_interval_with_handle(handle as i32);
+        let closure = INTERVALS.with(|i| i.borrow_mut().remove(&handle));
+        drop(closure);
+    }
+}
+
+thread_local! {
+    /// Every clock installed on this thread, indexed by clock id, the current one last. Clocks
+    /// that were replaced are kept, so timers scheduled on them are still cleared on them.
+    static CLOCKS: RefCell<Vec<Rc<dyn Clock>>> = RefCell::new(vec![Rc::new(BrowserClock)]);
+}
+
+/// Replace the clock used by the timer functions on the current thread. Timers scheduled on the
+/// previous clock are left as they are, and [`clear_timeout`](super::clear_timeout) and
+/// [`clear_interval`](super::clear_interval) still clear them there.
+pub fn set_clock(clock: impl Clock + 'static) {
+    let id = CLOCKS.with(|c| c.borrow().len() as u32);
+    if let Some(clock) = (&clock as &dyn Any).downcast_ref::<VirtualClock>() {
+        clock.installed_as(id);
+    }
+    CLOCKS.with(|c| c.borrow_mut().push(Rc::new(clock)));
+}
+
+/// The current clock and its id.
+pub(crate) fn current() -> (u32, Rc<dyn Clock>) {
+    CLOCKS.with(|c| {
+        let clocks = c.borrow();
+        ((clocks.len() - 1) as u32, clocks[clocks.len() - 1].clone())
+    })
+}
+
+/// The clock with this id, which scheduled the timer being cleared.
+pub(crate) fn get(id: u32) -> Rc<dyn Clock> {
+    CLOCKS.with(|c| c.borrow()[id as usize].clone())
+}
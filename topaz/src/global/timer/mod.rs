@@ -0,0 +1,371 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures::channel::{mpsc, oneshot};
+use futures::Stream;
+use wasm_bindgen::JsCast;
+use crate::global::callbacks::{self, Registry};
+use crate::global::window::web_sys_window;
+
+mod clock;
+mod virtual_clock;
+
+pub use clock::{set_clock, BrowserClock, Clock};
+pub use virtual_clock::{PendingTimer, VirtualClock};
+
+/// Identifies a timeout, and the [`Clock`] it was scheduled on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeoutId {
+    clock: u32,
+    handle: u32,
+}
+
+/// Identifies an interval, and the [`Clock`] it was scheduled on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntervalId {
+    clock: u32,
+    handle: u32,
+}
+
+/// Splitting this separate from the `set_timeout` function provides a monomorphization benefit
+/// The only fn that gets monomorphized is the `set_timeout` function, but it's body is extremely
+/// cheap.
+fn set_timeout_boxed(callback: Box<dyn FnOnce() + 'static>, millis: u32) -> TimeoutId {
+    let (clock, current) = clock::current();
+    TimeoutId { clock, handle: current.set_timeout(callback, millis) }
+}
+
+///
+/// # Examples
+///
+/// ```
+/// let t = topaz::global::set_timeout(|| {
+///      println!("Printed after 1s");
+/// }, 1000);
+/// ```
+// TODO measure if inline actually does anything here.
+#[inline]
+pub fn set_timeout(callback: impl FnOnce() + 'static, millis: u32) -> TimeoutId {
+    set_timeout_boxed(Box::new(callback), millis)
+}
+
+fn set_interval_boxed(callback: Box<dyn FnMut() + 'static>, timeout: u32) -> IntervalId {
+    let (clock, current) = clock::current();
+    IntervalId { clock, handle: current.set_interval(callback, timeout) }
+}
+
+#[inline]
+pub fn set_interval(callback: impl FnMut() + 'static, timeout: u32) -> IntervalId {
+    set_interval_boxed(Box::new(callback), timeout)
+}
+
+
+/// Clear the interval and free its closure.
+pub fn clear_interval(interval_id: IntervalId) {
+    clock::get(interval_id.clock).clear_interval(interval_id.handle);
+}
+
+pub fn clear_timeout(timeout_id: TimeoutId) {
+    clock::get(timeout_id.clock).clear_timeout(timeout_id.handle);
+}
+
+/// Clears its timeout when dropped. Returned by [`set_timeout_guard`].
+#[must_use = "the timeout is cleared as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct TimeoutGuard(Option<TimeoutId>);
+
+impl TimeoutGuard {
+    pub fn id(&self) -> TimeoutId {
+        self.0.expect("guard is live")
+    }
+
+    /// Let the timeout run even after the guard is dropped.
+    pub fn forget(mut self) -> TimeoutId {
+        self.0.take().expect("guard is live")
+    }
+}
+
+impl Drop for TimeoutGuard {
+    fn drop(&mut self) {
+        if let Some(timeout_id) = self.0.take() {
+            clear_timeout(timeout_id);
+        }
+    }
+}
+
+/// Like [`set_timeout`], but the timeout is cleared when the returned guard is dropped.
+/// Clearing a timeout that has already fired does nothing.
+///
+/// # Examples
+///
+/// ```no_run
+/// struct Toast {
+///     _dismiss: topaz::global::TimeoutGuard,
+/// }
+///
+/// let toast = Toast {
+///     _dismiss: topaz::global::set_timeout_guard(|| println!("Dismissed"), 3000),
+/// };
+/// // Dropping the toast early cancels the dismissal.
+/// drop(toast);
+/// ```
+pub fn set_timeout_guard(callback: impl FnOnce() + 'static, millis: u32) -> TimeoutGuard {
+    TimeoutGuard(Some(set_timeout(callback, millis)))
+}
+
+/// Clears its interval when dropped. Returned by [`set_interval_guard`].
+#[must_use = "the interval is cleared as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct IntervalGuard(Option<IntervalId>);
+
+impl IntervalGuard {
+    pub fn id(&self) -> IntervalId {
+        self.0.expect("guard is live")
+    }
+
+    /// Keep the interval running even after the guard is dropped.
+    pub fn forget(mut self) -> IntervalId {
+        self.0.take().expect("guard is live")
+    }
+}
+
+impl Drop for IntervalGuard {
+    fn drop(&mut self) {
+        if let Some(interval_id) = self.0.take() {
+            clear_interval(interval_id);
+        }
+    }
+}
+
+/// Like [`set_interval`], but the interval is cleared when the returned guard is dropped.
+pub fn set_interval_guard(callback: impl FnMut() + 'static, timeout: u32) -> IntervalGuard {
+    IntervalGuard(Some(set_interval(callback, timeout)))
+}
+
+/// Identifies a callback scheduled with [`request_idle_callback`], for
+/// [`cancel_idle_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleCallbackId(IdleHandle);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum IdleHandle {
+    Idle(u32),
+    Timeout(TimeoutId),
+}
+
+thread_local! {
+    static IDLE_CALLBACKS: Registry = RefCell::new(HashMap::new());
+}
+
+/// How long the fallback pretends the browser is idle for, the longest an idle period can last.
+const FALLBACK_IDLE_MILLIS: u64 = 50;
+
+/// Passed to a [`request_idle_callback`] callback. Do work in small pieces until
+/// `time_remaining()` runs out, then schedule another callback for the rest.
+pub struct IdleDeadline(DeadlineInner);
+
+enum DeadlineInner {
+    Browser(web_sys::IdleDeadline),
+    Fallback { end: u64, did_timeout: bool },
+}
+
+impl IdleDeadline {
+    /// How much of the current idle period is left. Zero once it's over, or if the callback ran
+    /// because its timeout expired.
+    pub fn time_remaining(&self) -> Duration {
+        match &self.0 {
+            DeadlineInner::Browser(deadline) => Duration::from_secs_f64(deadline.time_remaining().max(0.0) / 1000.0),
+            DeadlineInner::Fallback { end, .. } => Duration::from_millis(end.saturating_sub(now_millis())),
+        }
+    }
+
+    /// Whether the callback ran because its timeout expired rather than because the browser was
+    /// idle.
+    pub fn did_timeout(&self) -> bool {
+        match &self.0 {
+            DeadlineInner::Browser(deadline) => deadline.did_timeout(),
+            DeadlineInner::Fallback { did_timeout, .. } => *did_timeout,
+        }
+    }
+}
+
+/// Off wasm, and in workers, there's no `window` to ask, so the fallback is used.
+fn supports_idle_callback() -> bool {
+    if !cfg!(target_arch = "wasm32") {
+        return false;
+    }
+    match web_sys::window() {
+        Some(window) => js_sys::Reflect::has(&window, &"requestIdleCallback".into()).unwrap_or(false),
+        None => false,
+    }
+}
+
+fn request_idle_callback_boxed(callback: Box<dyn FnOnce(IdleDeadline) + 'static>, timeout: Option<u32>) -> IdleCallbackId {
+    if !supports_idle_callback() {
+        // Safari doesn't have `requestIdleCallback`. Run soon after the current task instead, and
+        // pretend the browser is idle for a short while.
+        let timeout_id = set_timeout(move || {
+            callback(IdleDeadline(DeadlineInner::Fallback {
+                end: now_millis() + FALLBACK_IDLE_MILLIS,
+                did_timeout: false,
+            }));
+        }, 1);
+        return IdleCallbackId(IdleHandle::Timeout(timeout_id));
+    }
+
+    let handle = callbacks::schedule(&IDLE_CALLBACKS, move |deadline| {
+        callback(IdleDeadline(DeadlineInner::Browser(deadline.unchecked_into())));
+    }, |function| {
+        let window = web_sys_window();
+        match timeout {
+            Some(timeout) => {
+                let options = web_sys::IdleRequestOptions::new();
+                options.set_timeout(timeout);
+                window.request_idle_callback_with_options(function, &options)
+            }
+            None => window.request_idle_callback(function),
+        }.expect("request_idle_callback failed")
+    });
+    IdleCallbackId(IdleHandle::Idle(handle))
+}
+
+/// Run `callback` when the browser is idle, for work that shouldn't compete with rendering or
+/// input, like flushing analytics or prefetching.
+///
+/// Where `requestIdleCallback` is missing, this falls back to `set_timeout`, with a deadline that
+/// gives the callback a short slice of time.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let mut queue = vec!["page_view", "click", "scroll"];
+/// topaz::global::request_idle_callback(move |deadline| {
+///     while deadline.time_remaining() > Duration::ZERO {
+///         match queue.pop() {
+///             Some(event) => println!("Sending {}", event),
+///             None => break,
+///         }
+///     }
+/// });
+/// ```
+#[inline]
+pub fn request_idle_callback(callback: impl FnOnce(IdleDeadline) + 'static) -> IdleCallbackId {
+    request_idle_callback_boxed(Box::new(callback), None)
+}
+
+/// Like [`request_idle_callback`], but run `callback` after `timeout` milliseconds even if the
+/// browser never becomes idle. [`IdleDeadline::did_timeout`] tells the two apart.
+#[inline]
+pub fn request_idle_callback_with_timeout(callback: impl FnOnce(IdleDeadline) + 'static, timeout: u32) -> IdleCallbackId {
+    request_idle_callback_boxed(Box::new(callback), Some(timeout))
+}
+
+pub fn cancel_idle_callback(id: IdleCallbackId) {
+    match id.0 {
+        IdleHandle::Idle(handle) => {
+            web_sys_window().cancel_idle_callback(handle);
+            callbacks::release(&IDLE_CALLBACKS, handle);
+        }
+        IdleHandle::Timeout(timeout_id) => clear_timeout(timeout_id),
+    }
+}
+
+fn duration_millis(duration: Duration) -> u32 {
+    duration.as_millis().min(i32::MAX as u128) as u32
+}
+
+/// The future returned by [`sleep`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Sleep {
+    rx: Option<oneshot::Receiver<()>>,
+    _guard: Option<TimeoutGuard>,
+}
+
+/// Wait for `duration`. Dropping the future before it completes clears the timeout.
+///
+/// A zero duration completes immediately, without going through the event loop.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// topaz::global::sleep(Duration::from_secs(1)).await;
+/// println!("Printed after 1s");
+/// # }
+/// ```
+pub fn sleep(duration: Duration) -> Sleep {
+    if duration.is_zero() {
+        return Sleep { rx: None, _guard: None };
+    }
+    let (tx, rx) = oneshot::channel();
+    let guard = set_timeout_guard(move || {
+        let _ = tx.send(());
+    }, duration_millis(duration));
+    Sleep { rx: Some(rx), _guard: Some(guard) }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(rx) = self.rx.as_mut() {
+            futures::ready!(Pin::new(rx).poll(cx)).ok();
+            self.rx = None;
+        }
+        Poll::Ready(())
+    }
+}
+
+/// The stream returned by [`interval`].
+#[must_use = "streams do nothing unless polled"]
+pub struct Interval {
+    rx: mpsc::UnboundedReceiver<()>,
+    _guard: IntervalGuard,
+}
+
+/// Yield every `duration`, starting one `duration` from now. Dropping the stream clears the
+/// interval.
+///
+/// Ticks that fire while the stream isn't being polled are queued, so a slow consumer sees every
+/// tick.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use futures::StreamExt;
+///
+/// # async fn run() {
+/// let mut ticks = topaz::global::interval(Duration::from_secs(5));
+/// while ticks.next().await.is_some() {
+///     println!("Polling...");
+/// }
+/// # }
+/// ```
+pub fn interval(duration: Duration) -> Interval {
+    let (tx, rx) = mpsc::unbounded();
+    let guard = set_interval_guard(move || {
+        let _ = tx.unbounded_send(());
+    }, duration_millis(duration));
+    Interval { rx, _guard: guard }
+}
+
+impl Stream for Interval {
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+/// Milliseconds since the Unix epoch, like `Date.now()`, according to the current [`Clock`].
+pub(crate) fn now_millis() -> u64 {
+    clock::current().1.now()
+}
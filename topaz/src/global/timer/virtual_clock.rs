@@ -0,0 +1,355 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use crate::global::timer::{Clock, IntervalId, TimeoutId};
+
+/// Like Jest's fake timers, give up on `run_until_idle` when timers keep scheduling more timers.
+const MAX_TIMERS: usize = 10_000;
+
+/// A [`Clock`] that only moves when told to, for testing timer-driven code natively and
+/// deterministically.
+///
+/// Timers run in the order they're due, and timers due at the same time run in the order they
+/// were scheduled, as in the browser. Time starts at the Unix epoch.
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+/// use std::time::Duration;
+/// use topaz::global::{set_clock, set_timeout, VirtualClock};
+///
+/// let clock = VirtualClock::new();
+/// set_clock(clock.clone());
+///
+/// let fired = Rc::new(Cell::new(false));
+/// set_timeout({
+///     let fired = fired.clone();
+///     move || fired.set(true)
+/// }, 1000);
+///
+/// clock.advance(Duration::from_millis(999));
+/// assert!(!fired.get());
+/// clock.advance(Duration::from_millis(1));
+/// assert!(fired.get());
+/// ```
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The id [`set_clock`](super::set_clock) gave this clock, for the ids in `pending`.
+    clock: u32,
+    now: u64,
+    next_handle: u32,
+    next_seq: u64,
+    timers: Vec<Timer>,
+    /// The interval whose callback is running, and whether it was cleared while running.
+    running: Option<(u32, bool)>,
+}
+
+struct Timer {
+    handle: u32,
+    due: u64,
+    seq: u64,
+    callback: Callback,
+}
+
+enum Callback {
+    Once(Box<dyn FnOnce()>),
+    Repeat { callback: Box<dyn FnMut()>, period: u64 },
+}
+
+/// A timer that hasn't fired yet, as reported by [`VirtualClock::pending`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingTimer {
+    Timeout {
+        id: TimeoutId,
+        remaining: Duration,
+    },
+    Interval {
+        id: IntervalId,
+        remaining: Duration,
+        period: Duration,
+    },
+}
+
+impl PendingTimer {
+    /// How long until the timer fires.
+    pub fn remaining(&self) -> Duration {
+        match self {
+            PendingTimer::Timeout { remaining, .. } | PendingTimer::Interval { remaining, .. } => *remaining,
+        }
+    }
+}
+
+impl State {
+    fn schedule(&mut self, callback: Callback, millis: u32) -> u32 {
+        self.next_handle += 1;
+        self.next_seq += 1;
+        self.timers.push(Timer {
+            handle: self.next_handle,
+            due: self.now + millis as u64,
+            seq: self.next_seq,
+            callback,
+        });
+        self.next_handle
+    }
+
+    fn clear(&mut self, handle: u32) -> Option<Timer> {
+        if let Some((running, cleared)) = &mut self.running {
+            if *running == handle {
+                *cleared = true;
+            }
+        }
+        let index = self.timers.iter().position(|timer| timer.handle == handle)?;
+        Some(self.timers.remove(index))
+    }
+
+    /// Remove the next timer due at or before `until`, and move the clock to when it's due.
+    fn pop(&mut self, until: Option<u64>) -> Option<Timer> {
+        let (index, timer) = self.timers.iter()
+            .enumerate()
+            .min_by_key(|(_, timer)| (timer.due, timer.seq))?;
+        if until.is_some_and(|until| timer.due > until) {
+            return None;
+        }
+        let timer = self.timers.remove(index);
+        self.now = self.now.max(timer.due);
+        Some(timer)
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current time, in milliseconds since the Unix epoch.
+    pub fn now(&self) -> u64 {
+        self.state.borrow().now
+    }
+
+    /// Move the clock forward by `duration`, running every timer that comes due along the way, in
+    /// order. Returns how many timers ran.
+    pub fn advance(&self, duration: Duration) -> usize {
+        let until = self.now() + duration.as_millis() as u64;
+        let mut count = 0;
+        while self.run_next(Some(until)) {
+            count += 1;
+        }
+        let mut state = self.state.borrow_mut();
+        state.now = state.now.max(until);
+        count
+    }
+
+    /// Run timers, moving the clock forward to each, until none are left. Returns how many timers
+    /// ran.
+    ///
+    /// # Panics
+    ///
+    /// An interval never goes idle, so this panics after running 10,000 timers.
+    pub fn run_until_idle(&self) -> usize {
+        let mut count = 0;
+        while self.run_next(None) {
+            count += 1;
+            assert!(count < MAX_TIMERS, "VirtualClock::run_until_idle ran {} timers, assuming an infinite loop", MAX_TIMERS);
+        }
+        count
+    }
+
+    pub(crate) fn installed_as(&self, clock: u32) {
+        self.state.borrow_mut().clock = clock;
+    }
+
+    /// The timers that haven't fired or been cleared, in the order they will fire.
+    pub fn pending(&self) -> Vec<PendingTimer> {
+        let state = self.state.borrow();
+        let mut timers: Vec<&Timer> = state.timers.iter().collect();
+        timers.sort_by_key(|timer| (timer.due, timer.seq));
+        timers.into_iter()
+            .map(|timer| {
+                let remaining = Duration::from_millis(timer.due - state.now);
+                match &timer.callback {
+                    Callback::Once(_) => PendingTimer::Timeout {
+                        id: TimeoutId { clock: state.clock, handle: timer.handle },
+                        remaining,
+                    },
+                    Callback::Repeat { period, .. } => PendingTimer::Interval {
+                        id: IntervalId { clock: state.clock, handle: timer.handle },
+                        remaining,
+                        period: Duration::from_millis(*period),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Run the next timer due at or before `until`. The state isn't borrowed while the callback
+    /// runs, so it can schedule and clear timers.
+    fn run_next(&self, until: Option<u64>) -> bool {
+        let timer = match self.state.borrow_mut().pop(until) {
+            Some(timer) => timer,
+            None => return false,
+        };
+        match timer.callback {
+            Callback::Once(callback) => callback(),
+            Callback::Repeat { mut callback, period } => {
+                self.state.borrow_mut().running = Some((timer.handle, false));
+                callback();
+                let mut state = self.state.borrow_mut();
+                if let Some((_, false)) = state.running.take() {
+                    state.next_seq += 1;
+                    let seq = state.next_seq;
+                    state.timers.push(Timer {
+                        handle: timer.handle,
+                        // Browsers clamp short intervals too, and a zero period would never let
+                        // the clock move on.
+                        due: timer.due + period.max(1),
+                        seq,
+                        callback: Callback::Repeat { callback, period },
+                    });
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        VirtualClock::now(self)
+    }
+
+    fn set_timeout(&self, callback: Box<dyn FnOnce()>, millis: u32) -> u32 {
+        self.state.borrow_mut().schedule(Callback::Once(callback), millis)
+    }
+
+    fn clear_timeout(&self, handle: u32) {
+        let removed = self.state.borrow_mut().clear(handle);
+        // Dropped after the state is released, since the callback may hold guards for other timers.
+        drop(removed);
+    }
+
+    fn set_interval(&self, callback: Box<dyn FnMut()>, millis: u32) -> u32 {
+        self.state.borrow_mut().schedule(Callback::Repeat { callback, period: millis as u64 }, millis)
+    }
+
+    fn clear_interval(&self, handle: u32) {
+        self.clear_timeout(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use futures::FutureExt;
+    use crate::global::timer::{cancel_idle_callback, clear_interval, clear_timeout, request_idle_callback, set_clock, set_interval, set_timeout, sleep};
+
+    fn install() -> (VirtualClock, Rc<RefCell<Vec<&'static str>>>) {
+        let clock = VirtualClock::new();
+        set_clock(clock.clone());
+        (clock, Rc::new(RefCell::new(Vec::new())))
+    }
+
+    fn push(log: &Rc<RefCell<Vec<&'static str>>>, entry: &'static str) -> impl FnMut() + 'static {
+        let log = log.clone();
+        move || log.borrow_mut().push(entry)
+    }
+
+    #[test]
+    fn test_order() {
+        let (clock, log) = install();
+        set_timeout(push(&log, "b"), 20);
+        set_timeout(push(&log, "a"), 10);
+        set_timeout(push(&log, "c"), 20);
+        let cleared = set_timeout(push(&log, "never"), 15);
+        clear_timeout(cleared);
+
+        assert_eq!(clock.pending().len(), 3);
+        assert_eq!(clock.pending()[0].remaining(), Duration::from_millis(10));
+        assert_eq!(clock.advance(Duration::from_millis(15)), 1);
+        assert_eq!(*log.borrow(), ["a"]);
+        assert_eq!(clock.run_until_idle(), 2);
+        assert_eq!(*log.borrow(), ["a", "b", "c"]);
+        assert_eq!(clock.now(), 20);
+        assert!(clock.pending().is_empty());
+    }
+
+    #[test]
+    fn test_interval() {
+        let (clock, log) = install();
+        let id = set_interval(push(&log, "tick"), 100);
+        assert_eq!(clock.advance(Duration::from_millis(350)), 3);
+        assert_eq!(clock.pending(), [PendingTimer::Interval {
+            id,
+            remaining: Duration::from_millis(50),
+            period: Duration::from_millis(100),
+        }]);
+        clear_interval(id);
+        assert_eq!(clock.advance(Duration::from_secs(1)), 0);
+        assert_eq!(log.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_clear_inside_callback() {
+        let (clock, log) = install();
+        let id = Rc::new(RefCell::new(None));
+        *id.borrow_mut() = Some(set_interval({
+            let id = id.clone();
+            let mut tick = push(&log, "tick");
+            move || {
+                tick();
+                clear_interval(id.borrow().unwrap());
+            }
+        }, 10));
+        set_timeout(push(&log, "nested"), 5);
+        assert_eq!(clock.run_until_idle(), 2);
+        assert_eq!(*log.borrow(), ["nested", "tick"]);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let (clock, _) = install();
+        let mut sleep = sleep(Duration::from_secs(1));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(sleep.now_or_never().is_some());
+        assert!(clock.pending().is_empty());
+    }
+
+    #[test]
+    fn test_idle_callback_fallback() {
+        let (clock, log) = install();
+        request_idle_callback({
+            let mut idle = push(&log, "idle");
+            move |deadline| {
+                assert!(!deadline.did_timeout());
+                idle();
+            }
+        });
+        let cancelled = request_idle_callback({
+            let mut never = push(&log, "never");
+            move |_| never()
+        });
+        cancel_idle_callback(cancelled);
+        assert_eq!(clock.run_until_idle(), 1);
+        assert_eq!(*log.borrow(), ["idle"]);
+    }
+
+    #[test]
+    fn test_clear_after_set_clock() {
+        let (first, log) = install();
+        let id = set_timeout(push(&log, "first"), 10);
+        let (second, _) = install();
+        set_timeout(push(&log, "second"), 10);
+        clear_timeout(id);
+        assert!(first.pending().is_empty());
+        assert_eq!(second.run_until_idle(), 1);
+        assert_eq!(*log.borrow(), ["second"]);
+    }
+}
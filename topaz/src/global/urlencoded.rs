@@ -0,0 +1,75 @@
+//! `application/x-www-form-urlencoded` encoding, shared by `location::Query` and `fetch::Form` so
+//! query strings and form bodies escape the same way.
+//! Spec: https://url.spec.whatwg.org/#application/x-www-form-urlencoded
+
+/// Percent-encode `s` onto `out`. Spaces become `+`.
+pub(crate) fn encode_into(s: &str, out: &mut String) {
+    for byte in s.bytes() {
+        match byte {
+            b'*' | b'-' | b'.' | b'_' | b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' => out.push(byte as char),
+            b' ' => out.push('+'),
+            _ => {
+                const HEX: &[u8; 16] = b"0123456789ABCDEF";
+                out.push('%');
+                out.push(HEX[(byte >> 4) as usize] as char);
+                out.push(HEX[(byte & 0xF) as usize] as char);
+            }
+        }
+    }
+}
+
+/// Serialize pairs as `key=value&key=value`, without a leading `?`.
+pub(crate) fn serialize<K: AsRef<str>, V: AsRef<str>>(pairs: impl IntoIterator<Item=(K, V)>) -> String {
+    let mut out = String::new();
+    for (key, value) in pairs {
+        if !out.is_empty() {
+            out.push('&');
+        }
+        encode_into(key.as_ref(), &mut out);
+        out.push('=');
+        encode_into(value.as_ref(), &mut out);
+    }
+    out
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        _ => None,
+    }
+}
+
+/// Percent-decode `s`. `+` becomes a space. A `%` that isn't followed by two hex digits is kept
+/// as is, and invalid UTF-8 becomes U+FFFD, like in the browser.
+pub(crate) fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 3) {
+            Some([b'%', high, low]) => hex_value(*high).zip(hex_value(*low)),
+            _ => None,
+        };
+        match (escaped, bytes[i]) {
+            (Some((high, low)), _) => {
+                out.push(high << 4 | low);
+                i += 3;
+                continue;
+            }
+            (None, b'+') => out.push(b' '),
+            (None, byte) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Parse `key=value&key=value`, without a leading `?`. A pair without `=` has an empty value.
+pub(crate) fn parse(s: &str) -> impl Iterator<Item=(String, String)> + '_ {
+    s.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (decode(key), decode(value))
+    })
+}
@@ -2,11 +2,29 @@
 #![cfg_attr(feature = "capture-print", feature(internal_output_capture))]
 mod observable;
 pub mod bind;
+pub mod print;
+pub mod logger;
+pub mod console;
+pub mod panic;
 pub mod global;
 mod dom;
 pub mod state2;
+mod builder;
 
+pub use builder::{mount, root, Builder, PanicHook, ReportFn};
 
+
+/// Redirect `std`'s `print!` family into [`print::stdout`].
+///
+/// `std` doesn't say when it writes to the capture buffer, so captured output is moved to the
+/// console after every callback Topaz runs (timers, animation frames, idle callbacks and event
+/// listeners), on [`print::flush`], on panic and when the page unloads. There's no polling, so
+/// output from other entry points, such as async tasks, shows up at the next of these. The
+/// [`println!`] family from this crate doesn't have this delay.
+///
+/// `std` captures stdout and stderr into the same buffer, so `std::eprintln!` can't be told apart
+/// from `std::println!` here and ends up in `console.log`. Use [`eprintln!`] and [`dbg!`] from this
+/// crate to write to `console.error`.
 #[cfg(feature = "capture-print")]
 pub fn hook_println() {
     use std::sync::Arc;
@@ -14,27 +32,11 @@ pub fn hook_println() {
 
     let console_buffer = Arc::new(Mutex::new(Vec::new()));
     std::io::set_output_capture(Some(console_buffer.clone()));
-
-
-    global::set_interval(move || {
-        let console_buffer = console_buffer.clone();
-        // let idx = idx.clone();
-        let mut z = match console_buffer.lock() {
-            Ok(z) => z,
-            Err(e) => return,
-        };
-        let s = std::str::from_utf8(&z[..]).unwrap();
-        if s.len() > 0 {
-            bind::log(s);
-            z.clear();
-        }
-    }, 16);
-
+    print::capture(console_buffer);
 }
 
+/// Set up Topaz with the defaults: print capture and the console panic hook according to the
+/// enabled features, and the `log` and `tracing` backends. Use [`Builder`] to configure these.
 pub fn start() {
-    #[cfg(feature = "capture-print")]
-    hook_println();
-    #[cfg(feature = "capture-panic")]
-    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-}
\ No newline at end of file
+    Builder::new().start();
+}
@@ -0,0 +1,114 @@
+use log::kv::{self, VisitSource};
+use crate::logger::{max_level, write, Level, LevelFilter, Record, Value};
+
+/// A [`log::Log`] that writes to the browser console. Installed by `topaz::start()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleLogger;
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+pub(crate) fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(to_log_filter(max_level()));
+    }
+}
+
+pub(crate) fn to_log_filter(filter: LevelFilter) -> log::LevelFilter {
+    match filter {
+        LevelFilter::Off => log::LevelFilter::Off,
+        LevelFilter::Error => log::LevelFilter::Error,
+        LevelFilter::Warn => log::LevelFilter::Warn,
+        LevelFilter::Info => log::LevelFilter::Info,
+        LevelFilter::Debug => log::LevelFilter::Debug,
+        LevelFilter::Trace => log::LevelFilter::Trace,
+    }
+}
+
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Trace,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Fields(Vec<(String, Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            Value::Bool(b)
+        } else if let Some(n) = value.to_i64() {
+            Value::I64(n)
+        } else if let Some(n) = value.to_u64() {
+            Value::U64(n)
+        } else if let Some(n) = value.to_f64() {
+            Value::F64(n)
+        } else {
+            Value::Str(value.to_string())
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+fn to_record<'a>(record: &'a log::Record) -> Record<'a> {
+    let mut fields = Fields::default();
+    let _ = record.key_values().visit(&mut fields);
+    Record {
+        level: record.level().into(),
+        target: record.target(),
+        message: record.args().to_string(),
+        fields: fields.0,
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        max_level().allows(metadata.level().into())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            write(to_record(record));
+        }
+    }
+
+    fn flush(&self) {
+        crate::print::flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_values() {
+        let kvs: &[(&str, kv::Value)] = &[
+            ("user", kv::Value::from("ferris")),
+            ("attempt", kv::Value::from(3i64)),
+            ("ok", kv::Value::from(true)),
+        ];
+        let args = format_args!("signed in");
+        let record = log::Record::builder()
+            .level(log::Level::Info)
+            .target("app::auth")
+            .args(args)
+            .key_values(&kvs)
+            .build();
+        let record = to_record(&record);
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.line(), "[app::auth] signed in");
+        assert_eq!(record.fields, [
+            ("user".to_string(), Value::Str("ferris".to_string())),
+            ("attempt".to_string(), Value::I64(3)),
+            ("ok".to_string(), Value::Bool(true)),
+        ]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Send [`log`](https://docs.rs/log) and [`tracing`](https://docs.rs/tracing) output to the
+//! browser console, behind the `log` and `tracing` features.
+//!
+//! `topaz::start()` installs whichever backends are enabled. Levels map to console methods:
+//!
+//! | Level            | Console method    |
+//! |------------------|-------------------|
+//! | `Error`          | `console.error`   |
+//! | `Warn`           | `console.warn`    |
+//! | `Info`           | `console.info`    |
+//! | `Debug`, `Trace` | `console.debug`   |
+//!
+//! Structured fields, i.e. `log`'s key-values and `tracing`'s fields, are passed to the console
+//! as an object, so they can be expanded instead of being flattened into the message.
+//!
+//! Records above [`max_level`] are dropped. It defaults to `Debug` in debug builds and `Info` in
+//! release builds, and can be changed at any time with [`set_max_level`]:
+//!
+//! ```
+//! use topaz::logger::{set_max_level, LevelFilter};
+//!
+//! set_max_level(LevelFilter::Warn);
+//! topaz::start();
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::bind;
+
+#[cfg(feature = "log")]
+mod log_backend;
+#[cfg(feature = "tracing")]
+mod tracing_backend;
+
+#[cfg(feature = "log")]
+pub use log_backend::ConsoleLogger;
+#[cfg(feature = "tracing")]
+pub use tracing_backend::ConsoleSubscriber;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// The most verbose [`Level`] that gets written, or `Off`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    const ALL: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+
+    pub fn allows(self, level: Level) -> bool {
+        level as usize <= self as usize
+    }
+}
+
+impl Default for LevelFilter {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        }
+    }
+}
+
+const UNSET: usize = usize::MAX;
+
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(UNSET);
+
+/// Change which records are written. Takes effect immediately, including for the `log` macros.
+pub fn set_max_level(filter: LevelFilter) {
+    MAX_LEVEL.store(filter as usize, Ordering::Relaxed);
+    #[cfg(feature = "log")]
+    log::set_max_level(log_backend::to_log_filter(filter));
+}
+
+pub fn max_level() -> LevelFilter {
+    match MAX_LEVEL.load(Ordering::Relaxed) {
+        UNSET => LevelFilter::default(),
+        level => LevelFilter::ALL[level],
+    }
+}
+
+/// Install the enabled backends as the global `log` logger and `tracing` subscriber. Called by
+/// `topaz::start()`. Does nothing for a backend if another logger or subscriber is already
+/// installed.
+pub fn init() {
+    #[cfg(feature = "log")]
+    log_backend::init();
+    #[cfg(feature = "tracing")]
+    tracing_backend::init();
+}
+
+/// A structured field value, kept as a number or bool where possible so the console shows it as
+/// one.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Str(String),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::I64(n) => write!(f, "{}", n),
+            Value::U64(n) => write!(f, "{}", n),
+            Value::F64(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl From<&Value> for wasm_bindgen::JsValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Str(s) => s.into(),
+            Value::I64(n) => (*n as f64).into(),
+            Value::U64(n) => (*n as f64).into(),
+            Value::F64(n) => (*n).into(),
+            Value::Bool(b) => (*b).into(),
+        }
+    }
+}
+
+/// One record, from either backend.
+pub(crate) struct Record<'a> {
+    pub level: Level,
+    pub target: &'a str,
+    pub message: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record<'_> {
+    /// The text passed as the first console argument.
+    fn line(&self) -> String {
+        format!("[{}] {}", self.target, self.message)
+    }
+}
+
+pub(crate) fn write(record: Record) {
+    // Keep log output in order with pending `print!` output.
+    crate::print::flush();
+    let line = record.line();
+    if !cfg!(target_arch = "wasm32") {
+        let fields: Vec<String> = record.fields.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        if fields.is_empty() {
+            eprintln!("{:5?} {}", record.level, line);
+        } else {
+            eprintln!("{:5?} {} {{{}}}", record.level, line, fields.join(", "));
+        }
+        return;
+    }
+    if record.fields.is_empty() {
+        match record.level {
+            Level::Error => bind::error(&line),
+            Level::Warn => bind::warn(&line),
+            Level::Info => bind::info(&line),
+            Level::Debug | Level::Trace => bind::debug(&line),
+        }
+        return;
+    }
+    let fields = js_sys::Object::new();
+    for (name, value) in &record.fields {
+        let _ = js_sys::Reflect::set(&fields, &name.as_str().into(), &value.into());
+    }
+    match record.level {
+        Level::Error => bind::error_with_value(&line, &fields),
+        Level::Warn => bind::warn_with_value(&line, &fields),
+        Level::Info => bind::info_with_value(&line, &fields),
+        Level::Debug | Level::Trace => bind::debug_with_value(&line, &fields),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_filter() {
+        assert!(LevelFilter::Warn.allows(Level::Error));
+        assert!(LevelFilter::Warn.allows(Level::Warn));
+        assert!(!LevelFilter::Warn.allows(Level::Info));
+        assert!(!LevelFilter::Off.allows(Level::Error));
+        assert!(LevelFilter::Trace.allows(Level::Trace));
+        for filter in LevelFilter::ALL {
+            assert_eq!(LevelFilter::ALL[filter as usize], filter);
+        }
+    }
+}
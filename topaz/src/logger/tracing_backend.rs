@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record as SpanRecord};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+use crate::logger::{max_level, write, Level, Record, Value};
+
+/// A [`tracing::Subscriber`] that writes events to the browser console. Installed by
+/// `topaz::start()`.
+///
+/// Events are prefixed with the names of the spans they're in, and carry the fields of those
+/// spans along with their own.
+#[derive(Default)]
+pub struct ConsoleSubscriber {
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, Span>>,
+}
+
+struct Span {
+    name: &'static str,
+    fields: Vec<(String, Value)>,
+    refs: usize,
+}
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn init() {
+    let _ = tracing::subscriber::set_global_default(ConsoleSubscriber::new());
+}
+
+impl ConsoleSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Span>> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Turn an event into a record, with the names and fields of the current spans, outermost
+    /// first.
+    fn to_record<'a>(&self, event: &Event<'a>) -> Record<'a> {
+        let metadata = event.metadata();
+        let mut prefix = String::new();
+        let mut fields = Vec::new();
+        let stack = STACK.with(|s| s.borrow().clone());
+        let spans = self.spans();
+        for span in stack.iter().filter_map(|id| spans.get(id)) {
+            prefix.push_str(span.name);
+            prefix.push_str(": ");
+            fields.extend(span.fields.iter().cloned());
+        }
+        drop(spans);
+        let mut visitor = Visitor::default();
+        event.record(&mut visitor);
+        fields.extend(visitor.fields);
+        Record {
+            level: (*metadata.level()).into(),
+            target: metadata.target(),
+            message: prefix + &visitor.message,
+            fields,
+        }
+    }
+}
+
+impl From<tracing::Level> for Level {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::DEBUG => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+/// Collects fields, pulling out the `message` field that the `tracing` macros use for the
+/// format string.
+#[derive(Default)]
+struct Visitor {
+    message: String,
+    fields: Vec<(String, Value)>,
+}
+
+impl Visitor {
+    fn push(&mut self, field: &Field, value: Value) {
+        self.fields.push((field.name().to_string(), value));
+    }
+}
+
+impl Visit for Visitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Value::F64(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Value::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Value::U64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Value::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.push(field, Value::Str(value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.push(field, Value::Str(format!("{:?}", value)));
+        }
+    }
+}
+
+impl Subscriber for ConsoleSubscriber {
+    /// The level filter can change at runtime, so callsites are checked every time.
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        max_level().allows((*metadata.level()).into())
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut visitor = Visitor::default();
+        attributes.record(&mut visitor);
+        self.spans().insert(id, Span {
+            name: attributes.metadata().name(),
+            fields: visitor.fields,
+            refs: 1,
+        });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &SpanRecord<'_>) {
+        let mut visitor = Visitor::default();
+        values.record(&mut visitor);
+        if let Some(span) = self.spans().get_mut(&span.into_u64()) {
+            for (name, value) in visitor.fields {
+                match span.fields.iter_mut().find(|(n, _)| *n == name) {
+                    Some(field) => field.1 = value,
+                    None => span.fields.push((name, value)),
+                }
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        write(self.to_record(event));
+    }
+
+    fn enter(&self, span: &Id) {
+        STACK.with(|s| s.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        STACK.with(|s| {
+            let mut stack = s.borrow_mut();
+            if let Some(i) = stack.iter().rposition(|id| *id == span.into_u64()) {
+                stack.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(span) = self.spans().get_mut(&span.into_u64()) {
+            span.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans();
+        let id = span.into_u64();
+        let closed = match spans.get_mut(&id) {
+            Some(span) => {
+                span.refs -= 1;
+                span.refs == 0
+            }
+            None => false,
+        };
+        if closed {
+            spans.remove(&id);
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    type Records = Arc<Mutex<Vec<(Level, String, Vec<(String, Value)>)>>>;
+
+    /// Forwards to a shared [`ConsoleSubscriber`] and keeps the records, so tests can look at them.
+    #[derive(Clone, Default)]
+    struct Recording {
+        inner: Arc<ConsoleSubscriber>,
+        records: Records,
+    }
+
+    impl Subscriber for Recording {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool { self.inner.enabled(metadata) }
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id { self.inner.new_span(attributes) }
+        fn record(&self, span: &Id, values: &SpanRecord<'_>) { self.inner.record(span, values) }
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let record = self.inner.to_record(event);
+            self.records.lock().unwrap().push((record.level, record.line(), record.fields));
+        }
+        fn enter(&self, span: &Id) { self.inner.enter(span) }
+        fn exit(&self, span: &Id) { self.inner.exit(span) }
+        fn clone_span(&self, span: &Id) -> Id { self.inner.clone_span(span) }
+        fn try_close(&self, span: Id) -> bool { self.inner.try_close(span) }
+    }
+
+    #[test]
+    fn test_spans_and_fields() {
+        let recording = Recording::default();
+        tracing::subscriber::with_default(recording.clone(), || {
+            let span = tracing::info_span!(target: "app", "checkout", cart = 7u64);
+            let _enter = span.enter();
+            tracing::warn!(target: "app", retries = 2i64, "payment slow");
+        });
+        assert!(recording.inner.spans().is_empty());
+        let records = recording.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        let (level, line, fields) = &records[0];
+        assert_eq!(*level, Level::Warn);
+        assert_eq!(line, "[app] checkout: payment slow");
+        assert_eq!(fields, &[
+            ("cart".to_string(), Value::U64(7)),
+            ("retries".to_string(), Value::I64(2)),
+        ]);
+    }
+}
@@ -0,0 +1,226 @@
+//! Show panics on the page, instead of leaving a frozen app with the details only in the console.
+//!
+//! In development, [`PanicHook::Overlay`](crate::PanicHook::Overlay) covers the page with the
+//! panic message, location and backtrace. In production,
+//! [`PanicHook::Fallback`](crate::PanicHook::Fallback) shows a "something went wrong" message
+//! and hands a [`PanicReport`] to a callback, e.g. to send it to an error tracker.
+//!
+//! ```no_run
+//! use topaz::{Builder, PanicHook};
+//!
+//! let panic_hook = if cfg!(debug_assertions) {
+//!     PanicHook::Overlay
+//! } else {
+//!     PanicHook::fallback(|report| {
+//!         // Send `report` to your error tracker.
+//!     })
+//! };
+//! Builder::new().panic_hook(panic_hook).start();
+//! ```
+//!
+//! Both still write the panic to the console first. Once a wasm module panics, it can't safely
+//! run again, so the page has to be reloaded; the buttons on the overlay and fallback are plain
+//! JavaScript for that reason.
+
+use std::fmt;
+use std::panic::PanicHookInfo;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    type StackError;
+
+    #[wasm_bindgen(constructor, js_class = "Error", js_name = Error)]
+    fn new() -> StackError;
+
+    #[wasm_bindgen(structural, method, getter)]
+    fn stack(error: &StackError) -> String;
+}
+
+/// What a panic hook knows about a panic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    pub message: String,
+    /// `file:line:column`, if known.
+    pub location: Option<String>,
+    /// The stack at the panic, with Rust symbols demangled. In the browser, this is the
+    /// JavaScript stack, which includes the wasm frames.
+    pub backtrace: String,
+}
+
+impl PanicReport {
+    pub fn from_info(info: &PanicHookInfo) -> Self {
+        let payload = info.payload();
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+        let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        let backtrace = if cfg!(target_arch = "wasm32") {
+            StackError::new().stack()
+        } else {
+            std::backtrace::Backtrace::force_capture().to_string()
+        };
+        PanicReport { message, location, backtrace: demangle_stack(&backtrace) }
+    }
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked")?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ":\n{}", self.message)
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.')
+}
+
+/// Demangle the Rust symbols in a stack trace, and drop the `::h0123456789abcdef` hashes from
+/// ones that are demangled already.
+fn demangle_stack(stack: &str) -> String {
+    stack.lines().map(demangle_line).collect::<Vec<_>>().join("\n")
+}
+
+fn demangle_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| is_symbol_char(c) || c == ':') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !(is_symbol_char(c) || c == ':')).unwrap_or(rest.len());
+        let token = &rest[..end];
+        match rustc_demangle::try_demangle(token) {
+            Ok(symbol) => out.push_str(&format!("{:#}", symbol)),
+            Err(_) => out.push_str(strip_hash(token)),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn strip_hash(symbol: &str) -> &str {
+    match symbol.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => path,
+        _ => symbol,
+    }
+}
+
+const OVERLAY_STYLE: &str = "position:fixed;inset:0;z-index:2147483647;overflow:auto;\
+    background:rgba(24,24,27,0.95);color:#fafafa;font:14px/1.5 ui-monospace,monospace;padding:32px;";
+const TITLE_STYLE: &str = "color:#f87171;font-size:18px;margin:0 0 8px;white-space:pre-wrap;";
+const PRE_STYLE: &str = "white-space:pre-wrap;margin:16px 0;color:#d4d4d8;";
+const BUTTON_STYLE: &str = "position:absolute;top:16px;right:16px;font:inherit;cursor:pointer;\
+    background:none;color:inherit;border:1px solid #52525b;border-radius:4px;padding:4px 12px;";
+const FALLBACK_STYLE: &str = "position:fixed;inset:0;z-index:2147483647;display:flex;\
+    flex-direction:column;align-items:center;justify-content:center;gap:16px;\
+    background:#fff;color:#18181b;font:16px/1.5 system-ui,sans-serif;text-align:center;";
+
+/// Create an element with some style and text, without going through `innerHTML`, since the
+/// panic message can contain anything.
+fn element(document: &web_sys::Document, tag: &str, style: &str, text: &str) -> Option<web_sys::Element> {
+    let element = document.create_element(tag).ok()?;
+    element.set_attribute("style", style).ok()?;
+    element.set_text_content(Some(text));
+    Some(element)
+}
+
+/// A button that runs `script` with `event` in scope. Rust closures can't be called after a
+/// panic, so this is JavaScript.
+fn button(document: &web_sys::Document, text: &str, script: &str) -> Option<web_sys::Element> {
+    let button = element(document, "button", BUTTON_STYLE, text)?;
+    let on_click = js_sys::Function::new_with_args("event", script);
+    button.add_event_listener_with_callback("click", &on_click).ok()?;
+    Some(button)
+}
+
+fn body() -> Option<(web_sys::Document, web_sys::HtmlElement)> {
+    let document = web_sys::window()?.document()?;
+    let body = document.body()?;
+    Some((document, body))
+}
+
+/// Cover the page with the panic message, location and backtrace, until dismissed.
+pub(crate) fn render_overlay(report: &PanicReport) -> Option<()> {
+    if !cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let (document, body) = body()?;
+    let overlay = element(&document, "div", OVERLAY_STYLE, "")?;
+    overlay.set_attribute("data-topaz-panic", "").ok()?;
+    overlay.set_attribute("role", "alert").ok()?;
+    let title = match &report.location {
+        Some(location) => format!("panicked at {}", location),
+        None => "panicked".to_string(),
+    };
+    let children = [
+        element(&document, "h1", TITLE_STYLE, &title)?,
+        element(&document, "pre", PRE_STYLE, &report.message)?,
+        element(&document, "pre", PRE_STYLE, &report.backtrace)?,
+        button(&document, "Dismiss", "event.currentTarget.parentElement.remove()")?,
+    ];
+    for child in children {
+        overlay.append_child(&child).ok()?;
+    }
+    body.append_child(&overlay).ok()?;
+    Some(())
+}
+
+/// Cover the page with a message for users, who can't do much with a backtrace.
+pub(crate) fn render_fallback() -> Option<()> {
+    if !cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let (document, body) = body()?;
+    let fallback = element(&document, "div", FALLBACK_STYLE, "")?;
+    fallback.set_attribute("data-topaz-panic", "").ok()?;
+    fallback.set_attribute("role", "alert").ok()?;
+    let message = element(&document, "p", "margin:0;font-size:20px;", "Something went wrong.")?;
+    fallback.append_child(&message).ok()?;
+    let reload = button(&document, "Reload", "location.reload()")?;
+    reload.set_attribute("style", "font:inherit;cursor:pointer;padding:6px 16px;").ok()?;
+    fallback.append_child(&reload).ok()?;
+    body.append_child(&fallback).ok()?;
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle_stack() {
+        let stack = "Error\n    at _ZN5topaz5panic4test17h0123456789abcdefE (app_bg.wasm:0x1234)\n    \
+            at topaz::global::timer::set_timeout::h0123456789abcdef (app_bg.wasm:0x5678)\n    \
+            at https://example.com/app.js:10:5";
+        assert_eq!(demangle_stack(stack), "Error\n    at topaz::panic::test (app_bg.wasm:0x1234)\n    \
+            at topaz::global::timer::set_timeout (app_bg.wasm:0x5678)\n    \
+            at https://example.com/app.js:10:5");
+    }
+
+    #[test]
+    fn test_report() {
+        let report = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let hook = std::panic::take_hook();
+        let thread = std::thread::current().id();
+        std::panic::set_hook(Box::new({
+            let report = report.clone();
+            // Other tests may panic while this hook is installed.
+            move |info| if std::thread::current().id() == thread {
+                *report.lock().unwrap() = Some(PanicReport::from_info(info));
+            }
+        }));
+        let _ = std::panic::catch_unwind(|| panic!("oh no: {}", 42));
+        std::panic::set_hook(hook);
+        let report = report.lock().unwrap().take().unwrap();
+        assert_eq!(report.message, "oh no: 42");
+        assert!(report.location.unwrap().starts_with("src/panic.rs:"));
+    }
+}
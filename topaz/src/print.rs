@@ -0,0 +1,392 @@
+//! `print!`-style macros that write to the browser console on stable Rust.
+//!
+//! On `wasm32-unknown-unknown`, `std`'s `println!` goes nowhere. The `capture-print` feature
+//! redirects it with `std::io::set_output_capture`, which requires nightly. These macros work on
+//! any toolchain: import them to shadow the `std` ones.
+//!
+//! ```
+//! use topaz::println;
+//!
+//! println!("Hello, {}!", "console");
+//! ```
+//!
+//! Output is line buffered, so every line becomes its own console entry. Anything after the last
+//! newline is written once the current task returns to the event loop, or on [`flush`]. See
+//! [`Flush`] to change when partial lines are written.
+//! `eprint!`, `eprintln!` and `dbg!` go to `console.error`, in order with `console.log` output.
+//! Partial lines are also flushed when the page unloads, and on panic once `topaz::start()` has
+//! run.
+//!
+//! Off wasm, e.g. in native tests or server side rendering, output goes to the process's stdout
+//! and stderr instead.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::Closure;
+use crate::bind;
+
+/// Which console method a line is written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// `console.log`
+    Stdout,
+    /// `console.error`
+    Stderr,
+}
+
+/// Splits written bytes into complete lines, keeping the partial last line for later.
+#[derive(Default)]
+struct LineBuffer {
+    partial: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(bytes);
+        let end = match self.partial.iter().rposition(|&b| b == b'\n') {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        String::from_utf8_lossy(&complete[..end])
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect()
+    }
+
+    fn take(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            return None;
+        }
+        let partial = std::mem::take(&mut self.partial);
+        Some(String::from_utf8_lossy(&partial).into_owned())
+    }
+}
+
+/// Both streams, so that lines come out in the order they were written: writing to one stream
+/// first flushes a partial line on the other.
+#[derive(Default)]
+struct Output {
+    stdout: LineBuffer,
+    stderr: LineBuffer,
+}
+
+impl Output {
+    fn buffer(&mut self, stream: Stream) -> &mut LineBuffer {
+        match stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        }
+    }
+
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> Vec<(Stream, String)> {
+        let mut lines = Vec::new();
+        if bytes.is_empty() {
+            return lines;
+        }
+        let other = match stream {
+            Stream::Stdout => Stream::Stderr,
+            Stream::Stderr => Stream::Stdout,
+        };
+        if let Some(partial) = self.buffer(other).take() {
+            lines.push((other, partial));
+        }
+        lines.extend(self.buffer(stream).push(bytes).into_iter().map(|line| (stream, line)));
+        lines
+    }
+
+    fn flush(&mut self, stream: Stream) -> Option<(Stream, String)> {
+        self.buffer(stream).take().map(|partial| (stream, partial))
+    }
+
+    fn has_partial(&self) -> bool {
+        !self.stdout.partial.is_empty() || !self.stderr.partial.is_empty()
+    }
+}
+
+/// When a partial line, i.e. output that doesn't end in a newline yet, is written out. Complete
+/// lines are always written immediately. Set with [`set_flush`] or `topaz::Builder::flush`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flush {
+    /// Once the code that's running returns to the event loop.
+    #[default]
+    Microtask,
+    /// After a delay, so output written in quick succession ends up on one line.
+    After(Duration),
+    /// Only on [`flush`], on panic and when the page unloads.
+    Manual,
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(Output::default());
+    static FLUSH_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+    static UNLOAD_HOOKED: Cell<bool> = const { Cell::new(false) };
+    static FLUSH: Cell<Flush> = const { Cell::new(Flush::Microtask) };
+    #[cfg(feature = "capture-print")]
+    static CAPTURE: RefCell<Option<std::sync::Arc<std::sync::Mutex<Vec<u8>>>>> = const { RefCell::new(None) };
+}
+
+pub fn set_flush(cadence: Flush) {
+    FLUSH.with(|f| f.set(cadence));
+}
+
+/// Flush partial lines later, according to the [`Flush`] cadence.
+fn schedule_flush() {
+    if !cfg!(target_arch = "wasm32") {
+        return;
+    }
+    flush_on_unload();
+    let cadence = FLUSH.with(|f| f.get());
+    if cadence == Flush::Manual || FLUSH_SCHEDULED.with(|s| s.replace(true)) {
+        return;
+    }
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Flush::After(delay) = cadence {
+            crate::global::sleep(delay).await;
+        }
+        FLUSH_SCHEDULED.with(|s| s.set(false));
+        flush();
+    });
+}
+
+/// Microtasks don't run once the page starts unloading, so flush on the way out.
+fn flush_on_unload() {
+    if UNLOAD_HOOKED.with(|h| h.replace(true)) {
+        return;
+    }
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    for event_name in ["beforeunload", "pagehide"] {
+        let closure = Closure::wrap(Box::new(flush) as Box<dyn FnMut()>);
+        let _ = window.add_event_listener_with_callback(event_name, closure.as_ref().unchecked_ref());
+        // Lives as long as the page.
+        closure.forget();
+    }
+}
+
+/// Take over a buffer that `std::io::set_output_capture` writes into. See `topaz::hook_println`.
+#[cfg(feature = "capture-print")]
+pub(crate) fn capture(buffer: std::sync::Arc<std::sync::Mutex<Vec<u8>>>) {
+    CAPTURE.with(|c| *c.borrow_mut() = Some(buffer));
+    if cfg!(target_arch = "wasm32") {
+        flush_on_unload();
+    }
+}
+
+/// Move output captured from `std`'s `print!` family into the console. The capture buffer can't
+/// tell us when it's written to, so this runs after every callback Topaz calls into, e.g. timers
+/// and event listeners, and on every [`flush`]. Finding output schedules another flush, so code
+/// that keeps printing, e.g. an async task, keeps being drained until it stops. Nothing runs on
+/// an idle page.
+pub(crate) fn flush_captured() {
+    #[cfg(feature = "capture-print")]
+    {
+        let captured = CAPTURE.with(|c| {
+            let capture = c.borrow();
+            // This also runs in the panic hook, possibly while the panicking thread holds the lock.
+            let mut buffer = capture.as_ref()?.try_lock().ok()?;
+            Some(std::mem::take(&mut *buffer))
+        });
+        if let Some(captured) = captured.filter(|c| !c.is_empty()) {
+            let _ = stdout().write_all(&captured);
+            schedule_flush();
+        }
+    }
+}
+
+fn emit(lines: impl IntoIterator<Item=(Stream, String)>) {
+    for (stream, line) in lines {
+        if cfg!(target_arch = "wasm32") {
+            match stream {
+                Stream::Stdout => bind::log(&line),
+                Stream::Stderr => bind::error(&line),
+            }
+        } else {
+            match stream {
+                Stream::Stdout => std::println!("{}", line),
+                Stream::Stderr => std::eprintln!("{}", line),
+            }
+        }
+    }
+}
+
+/// A [`Write`] sink for one of the console streams, for code that takes a writer instead of
+/// using the macros.
+///
+/// ```
+/// use std::io::Write;
+///
+/// let mut out = topaz::print::stdout();
+/// writeln!(out, "{} items", 3).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Console {
+    stream: Stream,
+}
+
+pub fn stdout() -> Console {
+    Console { stream: Stream::Stdout }
+}
+
+pub fn stderr() -> Console {
+    Console { stream: Stream::Stderr }
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (lines, has_partial) = OUTPUT.with(|o| {
+            let mut output = o.borrow_mut();
+            (output.write(self.stream, buf), output.has_partial())
+        });
+        emit(lines);
+        if has_partial {
+            schedule_flush();
+        }
+        Ok(buf.len())
+    }
+
+    /// Write out the partial line, if any.
+    fn flush(&mut self) -> io::Result<()> {
+        emit(OUTPUT.with(|o| o.borrow_mut().flush(self.stream)));
+        Ok(())
+    }
+}
+
+/// Write out partial lines on both streams, and anything captured with the `capture-print`
+/// feature.
+pub fn flush() {
+    flush_captured();
+    let _ = stdout().flush();
+    let _ = stderr().flush();
+}
+
+/// Whether the current panic hook already flushes, so starting twice doesn't stack wrappers. The
+/// panic hook is global, so this is too.
+static PANIC_HOOKED: AtomicBool = AtomicBool::new(false);
+
+/// Flush partial lines before the panic message is printed, wrapping the current panic hook,
+/// unless it's wrapped already.
+pub(crate) fn flush_on_panic() {
+    if PANIC_HOOKED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        flush();
+        hook(info);
+    }));
+}
+
+/// Note that the panic hook was replaced, so [`flush_on_panic`] wraps the new one.
+pub(crate) fn panic_hook_replaced() {
+    PANIC_HOOKED.store(false, Ordering::Relaxed);
+}
+
+#[doc(hidden)]
+pub fn _print(stream: Stream, args: fmt::Arguments) {
+    let _ = Console { stream }.write_fmt(args);
+}
+
+/// Like `std::print!`, but writes to `console.log`. See the [`print`](crate::print) module.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::print::_print($crate::print::Stream::Stdout, format_args!($($arg)*))
+    };
+}
+
+/// Like `std::println!`, but writes to `console.log`. See the [`print`](crate::print) module.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print::_print($crate::print::Stream::Stdout, format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::print::_print($crate::print::Stream::Stdout, format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Like `std::eprint!`, but writes to `console.error`. See the [`print`](crate::print) module.
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::print::_print($crate::print::Stream::Stderr, format_args!($($arg)*))
+    };
+}
+
+/// Like `std::eprintln!`, but writes to `console.error`. See the [`print`](crate::print) module.
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::print::_print($crate::print::Stream::Stderr, format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::print::_print($crate::print::Stream::Stderr, format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Like `std::dbg!`, but writes to `console.error`. See the [`print`](crate::print) module.
+#[macro_export]
+macro_rules! dbg {
+    () => {
+        $crate::eprintln!("[{}:{}]", file!(), line!())
+    };
+    ($val:expr $(,)?) => {
+        match $val {
+            tmp => {
+                $crate::eprintln!("[{}:{}] {} = {:#?}", file!(), line!(), stringify!($val), &tmp);
+                tmp
+            }
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::dbg!($val)),+,)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(b"Hello, ").is_empty());
+        assert_eq!(buffer.push(b"world!\r\nsecond\n\nthi"), ["Hello, world!", "second", ""]);
+        assert_eq!(buffer.take().as_deref(), Some("thi"));
+        assert_eq!(buffer.take(), None);
+    }
+
+    #[test]
+    fn test_interleaving() {
+        let mut output = Output::default();
+        assert!(output.write(Stream::Stdout, b"loading... ").is_empty());
+        assert_eq!(output.write(Stream::Stderr, b"failed\n"), [
+            (Stream::Stdout, "loading... ".to_string()),
+            (Stream::Stderr, "failed".to_string()),
+        ]);
+        assert_eq!(output.write(Stream::Stdout, b"retrying\n"), [(Stream::Stdout, "retrying".to_string())]);
+        assert_eq!(output.flush(Stream::Stderr), None);
+    }
+
+    #[test]
+    fn test_dbg() {
+        let (a, b) = crate::dbg!(1 + 1, "two");
+        assert_eq!((a, b), (2, "two"));
+    }
+
+    #[test]
+    fn test_split_utf8() {
+        let mut buffer = LineBuffer::default();
+        let bytes = "日本\n".as_bytes();
+        assert!(buffer.push(&bytes[..2]).is_empty());
+        assert_eq!(buffer.push(&bytes[2..]), ["日本"]);
+    }
+}